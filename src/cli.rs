@@ -1,5 +1,10 @@
+use chrono::NaiveDate;
 use clap::crate_version;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use snow_report_mapper::{
+    Auth, Compression, DedupeStrategy, Detector, Encoding, EntryFormat, IncidentOrder, InputFormat,
+    LabelMode, Normalization, OutputFormat, PromptField, SidecarFormat,
+};
 
 /// CLI arguments
 #[derive(Parser, Debug)]
@@ -8,25 +13,318 @@ use clap::Parser;
 #[clap(propagate_version = true)]
 #[clap(about = "Prepares OpenAI refinement data, based on ServiceNOW incidents")]
 #[clap(long_about = None)]
+#[clap(args_conflicts_with_subcommands = true)]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Args {
-    /// Filepath to the SNOW incidents export
-    #[clap(value_parser)]
-    pub file_incidents: String,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// Filepath or glob pattern (e.g. `'incidents_2023_*.json'`) of the SNOW incidents export(s)
+    #[clap(value_parser, required = true)]
+    pub file_incidents: Option<String>,
     /// Filepath to the SNOW export of the assignment groups
-    #[clap(value_parser)]
-    pub file_assignment_groups: String,
+    #[clap(value_parser, required = true)]
+    pub file_assignment_groups: Option<String>,
     /// Filepath where the mapped training file should be stored to
-    #[clap(value_parser)]
-    pub file_output: String,
+    #[clap(value_parser, required = true)]
+    pub file_output: Option<String>,
+    /// Additional SNOW incidents export(s) (filepath or glob pattern), that are merged with the first one. Incidents
+    /// that occur in multiple exports are only kept once (by `sys_id`). Can be repeated
+    #[clap(long = "incidents", value_parser, value_name = "FILE_INCIDENTS")]
+    pub more_incidents: Vec<String>,
     /// Trim the amount of incidents per assigment group by the given amount
     #[clap(short, long, value_parser)]
     pub trim: Option<usize>,
+    /// Format of the exports. Detected by the file extension, if not set
+    #[clap(short, long, value_enum)]
+    pub input_format: Option<InputFormat>,
+    /// Compression of the exports. Detected by the file extension (`.gz`, `.zst`), if not set
+    #[clap(long, value_enum)]
+    pub input_compression: Option<Compression>,
+    /// Maps a field to a column of CSV exports (e.g. `short_description="Short description"`). Can be repeated.
+    /// Fields: sys_id, number, short_description, description, category, subcategory, cmdb_ci, business_service,
+    /// location, assignment_group, opened_at, sys_created_on, name, user_name, email. Prefix a field with `incidents.`,
+    /// `groups.` or `users.` to map it for a single export only (e.g. `groups.sys_id="Sys ID"`)
+    #[clap(short, long = "column", value_parser = parse_column_mapping)]
+    pub columns: Vec<(String, String)>,
+    /// Strategy to resolve titles that were assigned to different groups
+    #[clap(short, long, value_enum, default_value = "last")]
+    pub dedupe: DedupeStrategy,
+    /// De-dupe near-identical titles as well, whose similarity (`0` to `1`, e.g. `0.8`) reaches the given threshold.
+    /// Titles are compared case-insensitively, with numbers and punctuation being ignored
+    #[clap(long, value_parser)]
+    pub similarity: Option<f64>,
+    /// Order in which the incidents are processed and written (`shuffle` uses `--seed`)
+    #[clap(short, long, value_enum, default_value = "number")]
+    pub order: IncidentOrder,
+    /// Drop assignment groups with less incidents than the given amount
+    #[clap(long, value_parser)]
+    pub min_samples: Option<usize>,
+    /// Randomly undersample assignment groups with more incidents than the given amount (uses `--seed`)
+    #[clap(long, value_parser)]
+    pub max_samples: Option<usize>,
+    /// Randomly undersample assignment groups with more incidents than the given multiple of the median amount of
+    /// incidents per group (uses `--seed`)
+    #[clap(long, value_parser)]
+    pub max_median_factor: Option<f64>,
+    /// Oversample assignment groups with less training entries to the given amount, by duplicating random entries
+    /// (uses `--seed`)
+    #[clap(long, value_parser)]
+    pub oversample: Option<usize>,
+    /// Format of the output file
+    #[clap(short, long, value_enum, default_value = "json")]
+    pub format: OutputFormat,
+    /// Compression of the output file(s). Detected by the file extension (`.gz`, `.zst`), if not set
+    #[clap(short = 'z', long, value_enum)]
+    pub compression: Option<Compression>,
+    /// Field of the incidents that is added to the prompts, optionally preceded by a label (e.g. `category=Category`).
+    /// Can be repeated, the fields are added line by line in the given order. Only the title is used, if not set.
+    /// Fields: number, short_description, description, category, subcategory, cmdb_ci, business_service, location
+    #[clap(short, long = "prompt-field", value_parser = parse_prompt_field)]
+    pub prompt_fields: Vec<PromptField>,
+    /// Format of the fine-tuning entries
+    #[clap(short, long, value_enum, default_value = "completion")]
+    pub mode: EntryFormat,
+    /// System prompt for the entries in `chat` mode
+    #[clap(long, value_parser)]
+    pub system_prompt: Option<String>,
+    /// Template of the prompts, e.g. `$'{{short_description}}\n\n###\n\n'`. Placeholders: `{{prompt}}` (built from
+    /// the prompt fields), `{{category}}`, `{{label}}`, `{{group.name}}`, `{{group.sys_id}}` and the fields of the
+    /// incidents (e.g. `{{description}}` or `{{incident.category}}`)
+    #[clap(long, value_parser)]
+    pub prompt_template: Option<String>,
+    /// Template of the completions, e.g. `' {{label}}'`. Same placeholders as for the prompts
+    #[clap(long, value_parser)]
+    pub completion_template: Option<String>,
+    /// How the assignment groups are labeled in the completions (`{{label}}`). Labels other than `id` are kept in the
+    /// label map, to stay stable between runs
+    #[clap(long, value_enum, default_value = "id")]
+    pub label_mode: LabelMode,
+    /// Stop sequence, that is appended to the completions in `completion` mode (e.g. `' END'` or `$'\n'`)
+    #[clap(long, value_parser)]
+    pub stop_sequence: Option<String>,
+    /// Filepath to a JSON file with the templates (`{"prompt": "...", "completion": "..."}`)
+    #[clap(long, value_parser)]
+    pub template_file: Option<String>,
+    /// Normalize the fields of the incidents, before the prompts are created and the titles are de-duped. Can be
+    /// repeated or comma-separated, the steps are always applied in the order of the possible values
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub normalize: Vec<Normalization>,
+    /// Redact personal data from the fields of the incidents, by replacing it with a placeholder (e.g. `[EMAIL]`).
+    /// Can be repeated or comma-separated
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub redact: Vec<Detector>,
+    /// Custom redaction rule in the format `NAME=REGEX` (e.g. `employee_id=\bE\d{6}\b`), whose matches are replaced
+    /// by `[NAME]`. Can be repeated, the rules are applied before the built-in detectors (except for `url` and `email`)
+    #[clap(long = "redact-rule", value_parser = parse_redaction_rule)]
+    pub redact_rules: Vec<(String, String)>,
+    /// Filepath to an export of the users (`sys_user` with `name`, `user_name` and `email`), whose names, login IDs and
+    /// email addresses are redacted from the fields of the incidents. Same formats as the other exports
+    #[clap(long, value_parser)]
+    pub users: Option<String>,
+    /// Filepath to a BPE vocabulary in the format of tiktoken (e.g. `cl100k_base.tiktoken`), to count the tokens of the
+    /// entries (listed with `--stats`)
+    #[clap(long, value_parser)]
+    pub vocab: Option<String>,
+    /// Encoding of the vocabulary, which determines how texts are split into words
+    #[clap(long, value_enum, default_value = "cl100k")]
+    pub encoding: Encoding,
+    /// Truncate the prompt fields (`{{prompt}}`) to the given amount of tokens, including the rest of the prompt
+    /// template (requires `--vocab`)
+    #[clap(long, value_parser)]
+    pub max_prompt_tokens: Option<usize>,
+    /// Amount of epochs the model is trained for, to estimate the costs of the training
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub epochs: u32,
+    /// Price per 1M training tokens, to estimate the costs of the training (requires `--vocab`)
+    #[clap(long, value_parser)]
+    pub price: Option<f64>,
+    /// Filepath to the label map, which keeps the category IDs stable between runs (created if missing)
+    #[clap(short, long, value_parser)]
+    pub label_map: Option<String>,
+    /// Format of the sidecar file, that links the category IDs to the assignment groups
+    #[clap(long, value_enum, default_value = "json")]
+    pub sidecar_format: SidecarFormat,
+    /// Ratio of entries that are split off into a validation file (e.g. `0.1`)
+    #[clap(long, value_parser)]
+    pub validation_ratio: Option<f64>,
+    /// Ratio of entries that are split off into a test file (e.g. `0.1`)
+    #[clap(long, value_parser)]
+    pub test_ratio: Option<f64>,
+    /// Cut-off date (`YYYY-MM-DD`) for a chronological split. Incidents opened on or after it are written to the
+    /// test file
+    #[clap(long, value_parser, conflicts_with = "test-ratio")]
+    pub split_date: Option<NaiveDate>,
+    /// Seed for all randomized steps, to make the output reproducible
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub seed: u64,
     /// Verbose output
     #[clap(short, long)]
     pub verbose: bool,
     /// Prints additional statistics
     #[clap(short, long)]
     pub stats: bool,
+    /// Validates the entries before writing them (like the `validate` subcommand). Warnings are printed, errors abort
+    #[clap(long)]
+    pub validate: bool,
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Fetches the incidents and assignment groups from the Table API of an instance and stores them as JSON exports
+    Fetch(Box<FetchArgs>),
+    /// Validates a fine-tuning file (JSON array or JSONL) for common mistakes, like missing separators, duplicates or
+    /// classes with too few examples. Exits with an error, if the file can't be used as it is
+    Validate(ValidateArgs),
+}
+
+/// CLI arguments of the `fetch` subcommand
+#[derive(clap::Args, Debug)]
+pub struct FetchArgs {
+    /// Filepath where the SNOW incidents export should be stored to
+    #[clap(value_parser)]
+    pub file_incidents: String,
+    /// Filepath where the SNOW export of the assignment groups should be stored to
+    #[clap(value_parser)]
+    pub file_assignment_groups: String,
+    /// Base URL of the instance (e.g. `https://example.service-now.com`)
+    #[clap(long, value_parser, env = "SNOW_INSTANCE")]
+    pub instance: String,
+    /// User for basic authentication or the OAuth password grant
+    #[clap(short, long, value_parser, env = "SNOW_USER")]
+    pub user: Option<String>,
+    /// Password for basic authentication or the OAuth password grant
+    #[clap(
+        short,
+        long,
+        value_parser,
+        env = "SNOW_PASSWORD",
+        hide_env_values = true
+    )]
+    pub password: Option<String>,
+    /// OAuth bearer token, instead of user and password
+    #[clap(long, value_parser, env = "SNOW_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+    /// OAuth client ID, to obtain a bearer token with user and password
+    #[clap(long, value_parser, env = "SNOW_CLIENT_ID", requires = "client-secret")]
+    pub client_id: Option<String>,
+    /// OAuth client secret, to obtain a bearer token with user and password
+    #[clap(long, value_parser, env = "SNOW_CLIENT_SECRET", hide_env_values = true)]
+    pub client_secret: Option<String>,
+    /// Encoded query to filter the incidents (e.g. `opened_at>=2023-01-01`)
+    #[clap(long, value_parser)]
+    pub incident_query: Option<String>,
+    /// Encoded query to filter the assignment groups
+    #[clap(long, value_parser)]
+    pub group_query: Option<String>,
+    /// Amount of records per request
+    #[clap(long, value_parser, default_value_t = 1000)]
+    pub page_size: usize,
+    /// Amount of retries of failed or rate-limited requests
+    #[clap(long, value_parser, default_value_t = 3)]
+    pub retries: u32,
+    /// Verbose output
+    #[clap(short, long)]
+    pub verbose: bool,
+}
+
+/// CLI arguments of the `validate` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Filepath to the fine-tuning file. Compressed files (`.gz`, `.zst`) are decompressed
+    #[clap(value_parser)]
+    pub file: String,
+}
+
+impl FetchArgs {
+    /// Determines the authentication from the arguments
+    ///
+    /// # Returns
+    ///
+    /// The authentication. A token takes precedence over the OAuth password grant, which takes precedence over basic
+    /// authentication
+    ///
+    /// # Bails out when
+    ///
+    /// - neither a token nor user and password are given
+    pub fn auth(&self) -> anyhow::Result<Auth> {
+        if let Some(token) = &self.token {
+            return Ok(Auth::Token(token.clone()));
+        }
+        let (user, password) = match (&self.user, &self.password) {
+            (Some(user), Some(password)) => (user.clone(), password.clone()),
+            _ => anyhow::bail!("Either a token or user and password are required"),
+        };
+        match (&self.client_id, &self.client_secret) {
+            (Some(client_id), Some(client_secret)) => Ok(Auth::OAuth {
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                user,
+                password,
+            }),
+            _ => Ok(Auth::Basic { user, password }),
+        }
+    }
+}
+
+/// Parses a mapping between field and column name
+///
+/// # Arguments
+///
+/// - `mapping`: Mapping in the format `FIELD=COLUMN`
+///
+/// # Returns
+///
+/// Tuple of field and column name
+///
+/// # Bails out when
+///
+/// - the mapping doesn't contain a `=`
+fn parse_column_mapping(mapping: &str) -> Result<(String, String), String> {
+    match mapping.split_once('=') {
+        Some((field, column)) => Ok((field.trim().to_string(), column.to_string())),
+        None => Err(format!("Expected FIELD=COLUMN, got '{}'", mapping)),
+    }
+}
+
+/// Parses a custom redaction rule
+///
+/// # Arguments
+///
+/// - `rule`: Rule in the format `NAME=REGEX`
+///
+/// # Returns
+///
+/// Tuple of name and regular expression
+///
+/// # Bails out when
+///
+/// - the rule doesn't contain a `=`
+fn parse_redaction_rule(rule: &str) -> Result<(String, String), String> {
+    match rule.split_once('=') {
+        Some((name, pattern)) => Ok((name.trim().to_string(), pattern.to_string())),
+        None => Err(format!("Expected NAME=REGEX, got '{}'", rule)),
+    }
+}
+
+/// Parses a prompt field with an optional label
+///
+/// # Arguments
+///
+/// - `prompt_field`: Prompt field in the format `FIELD` or `FIELD=LABEL`
+///
+/// # Returns
+///
+/// The prompt field
+fn parse_prompt_field(prompt_field: &str) -> Result<PromptField, String> {
+    let (name, label) = match prompt_field.split_once('=') {
+        Some((name, label)) => (name, Some(label.to_string())),
+        None => (prompt_field, None),
+    };
+    Ok(PromptField {
+        name: name.trim().to_string(),
+        label,
+    })
 }
 
 /// Parses the CLI arguments
@@ -0,0 +1,296 @@
+/// Transparent (de-)compression of the files that are read and written
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Compression level of zstd. `0` is zstd's default level
+const ZSTD_LEVEL: i32 = 0;
+
+/// Compression of a file
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed
+    None,
+    /// gzip (`.gz`)
+    Gzip,
+    /// Zstandard (`.zst`)
+    Zstd,
+}
+
+/// Determines the compression of a file
+///
+/// # Arguments
+///
+/// - `filename`: Filepath of the file
+/// - `compression`: Explicitly configured compression, which takes precedence
+///
+/// # Returns
+///
+/// The configured compression or the compression, that matches the file extension. Falls back to no compression
+pub fn detect_compression(filename: &str, compression: Option<Compression>) -> Compression {
+    if let Some(compression) = compression {
+        return compression;
+    }
+    match compression_extension(filename) {
+        Some((_, compression)) => compression,
+        None => Compression::None,
+    }
+}
+
+/// Splits off the extension of a compressed file (e.g. `export.json.gz` becomes `export.json` and `.gz`)
+///
+/// # Arguments
+///
+/// - `filename`: Filepath of the file
+///
+/// # Returns
+///
+/// Tuple with:
+///
+/// - The filepath without the compression extension
+/// - The compression extension, including the leading dot. Empty, if the file has none
+pub fn split_compression_extension(filename: &str) -> (&str, &str) {
+    match compression_extension(filename) {
+        Some((extension_len, _)) => filename.split_at(filename.len() - extension_len),
+        None => (filename, ""),
+    }
+}
+
+/// Opens a file for reading, decompressing it on the fly
+///
+/// # Arguments
+///
+/// - `filename`: Filepath of the file
+/// - `compression`: Explicitly configured compression. Detected by the file extension, if not set
+///
+/// # Returns
+///
+/// Buffered reader of the decompressed content
+///
+/// # Bails out when
+///
+/// - the file can't be opened
+/// - the zstd decoder can't be initialized
+pub fn open_file(filename: &str, compression: Option<Compression>) -> Result<Box<dyn BufRead>> {
+    let file = File::open(filename).with_context(|| format!("Can't read {}", filename))?;
+    let reader: Box<dyn BufRead> = match detect_compression(filename, compression) {
+        Compression::None => Box::new(BufReader::new(file)),
+        // Concatenated gzip members (e.g. of appended archives) are read as one stream
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file)))),
+        Compression::Zstd => Box::new(BufReader::new(
+            zstd::Decoder::new(file).with_context(|| format!("Can't decompress {}", filename))?,
+        )),
+    };
+    Ok(reader)
+}
+
+/// Reads the whole (decompressed) content of a file
+///
+/// # Arguments
+///
+/// - `filename`: Filepath of the file
+/// - `compression`: Explicitly configured compression. Detected by the file extension, if not set
+///
+/// # Returns
+///
+/// The decompressed content
+///
+/// # Bails out when
+///
+/// - the file can't be read or decompressed
+/// - the content isn't valid UTF-8
+pub fn read_to_string(filename: &str, compression: Option<Compression>) -> Result<String> {
+    let mut content = String::new();
+    open_file(filename, compression)?
+        .read_to_string(&mut content)
+        .with_context(|| format!("Can't read {}", filename))?;
+    Ok(content)
+}
+
+/// Writer of a file, that compresses the content on the fly
+pub enum FileWriter {
+    /// Uncompressed
+    None(BufWriter<File>),
+    /// gzip
+    Gzip(GzEncoder<BufWriter<File>>),
+    /// Zstandard
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl FileWriter {
+    /// Creates (or truncates) a file for writing
+    ///
+    /// # Arguments
+    ///
+    /// - `filename`: Filepath of the file
+    /// - `compression`: Explicitly configured compression. Detected by the file extension, if not set
+    ///
+    /// # Bails out when
+    ///
+    /// - the file can't be created
+    /// - the zstd encoder can't be initialized
+    pub fn create(filename: &str, compression: Option<Compression>) -> Result<Self> {
+        let file = BufWriter::new(
+            File::create(filename)
+                .with_context(|| format!("Failed to write to: '{}'", filename))?,
+        );
+        let writer = match detect_compression(filename, compression) {
+            Compression::None => FileWriter::None(file),
+            Compression::Gzip => {
+                FileWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Compression::Zstd => FileWriter::Zstd(
+                zstd::Encoder::new(file, ZSTD_LEVEL)
+                    .with_context(|| format!("Failed to compress: '{}'", filename))?,
+            ),
+        };
+        Ok(writer)
+    }
+
+    /// Completes the compressed stream and flushes all content to the file. Has to be called after the last write,
+    /// as errors would get lost otherwise
+    ///
+    /// # Bails out when
+    ///
+    /// - the content can't be written
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            FileWriter::None(file) => file,
+            FileWriter::Gzip(encoder) => encoder.finish()?,
+            FileWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::None(file) => file.write(buf),
+            FileWriter::Gzip(encoder) => encoder.write(buf),
+            FileWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::None(file) => file.flush(),
+            FileWriter::Gzip(encoder) => encoder.flush(),
+            FileWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Gets the compression extension of a file
+///
+/// # Arguments
+///
+/// - `filename`: Filepath of the file
+///
+/// # Returns
+///
+/// Length of the extension (including the leading dot) and the matching compression, if the file has a compression
+/// extension
+fn compression_extension(filename: &str) -> Option<(usize, Compression)> {
+    let extension = Path::new(filename).extension()?.to_string_lossy();
+    let compression = match extension.to_lowercase().as_str() {
+        "gz" | "gzip" => Compression::Gzip,
+        "zst" | "zstd" => Compression::Zstd,
+        _ => return None,
+    };
+    Some((extension.len() + 1, compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Gets a filepath within the temporary directory, that is unique for the test process
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "snow_report_mapper_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn write_and_read(name: &str, compression: Option<Compression>) -> (Vec<u8>, String) {
+        let path = temp_file(name);
+        let filename = path.to_str().unwrap();
+        let mut writer = FileWriter::create(filename, compression).unwrap();
+        writer
+            .write_all("{\"prompt\": \"Printer jammed\"}\n".as_bytes())
+            .unwrap();
+        writer.finish().unwrap();
+        let raw = std::fs::read(&path).unwrap();
+        let content = read_to_string(filename, compression).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        (raw, content)
+    }
+
+    #[test]
+    fn detects_compression_by_extension() {
+        assert_eq!(
+            detect_compression("train.jsonl.gz", None),
+            Compression::Gzip
+        );
+        assert_eq!(
+            detect_compression("train.JSONL.ZST", None),
+            Compression::Zstd
+        );
+        assert_eq!(detect_compression("train.jsonl", None), Compression::None);
+        assert_eq!(
+            detect_compression("train.jsonl", Some(Compression::Gzip)),
+            Compression::Gzip
+        );
+        assert_eq!(
+            split_compression_extension("out/train.jsonl.gz"),
+            ("out/train.jsonl", ".gz")
+        );
+        assert_eq!(
+            split_compression_extension("train.jsonl.zstd"),
+            ("train.jsonl", ".zstd")
+        );
+        assert_eq!(
+            split_compression_extension("train.jsonl"),
+            ("train.jsonl", "")
+        );
+    }
+
+    #[test]
+    fn round_trips_compressed_files() {
+        let content = "{\"prompt\": \"Printer jammed\"}\n";
+        let (raw, read) = write_and_read("round_trip.jsonl.gz", None);
+        assert_eq!(raw[..2], [0x1f, 0x8b]);
+        assert_eq!(read, content);
+        let (raw, read) = write_and_read("round_trip.jsonl.zst", None);
+        assert_eq!(raw[..4], [0x28, 0xb5, 0x2f, 0xfd]);
+        assert_eq!(read, content);
+        let (raw, read) = write_and_read("round_trip.jsonl", None);
+        assert_eq!(raw, content.as_bytes());
+        assert_eq!(read, content);
+        // The configured compression takes precedence over the extension
+        let (raw, read) = write_and_read("round_trip.json", Some(Compression::Gzip));
+        assert_eq!(raw[..2], [0x1f, 0x8b]);
+        assert_eq!(read, content);
+    }
+
+    #[test]
+    fn reads_concatenated_gzip_members() {
+        let path = temp_file("concatenated.json.gz");
+        let mut raw: Vec<u8> = Vec::new();
+        for member in ["[1,", "2]"] {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(member.as_bytes()).unwrap();
+            raw.extend(encoder.finish().unwrap());
+        }
+        std::fs::write(&path, raw).unwrap();
+        let content = read_to_string(path.to_str().unwrap(), None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content, "[1,2]");
+    }
+}
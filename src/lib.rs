@@ -1,15 +1,44 @@
 /// Contains the application logic for the ServiceNow mapper
+use crate::loaders::label_map::LabelMap;
 use crate::loaders::servicenow;
-use crate::servicenow::{AssignmentGroup, Incident};
+use crate::loaders::servicenow_api;
+use crate::loaders::servicenow_csv;
+use crate::loaders::servicenow_xml;
+use crate::loaders::template::TemplateConfig;
+use crate::mappers::output::{FineTuningEntry, TextProcessors};
+use crate::mappers::template::EntryTemplates;
+use crate::mappers::tokenizer::Tokenizer;
+use crate::mappers::validate::Severity;
+use crate::servicenow::{AssignmentGroup, Incident, TitleConflict, User};
 use crate::stats::Stats;
-use anyhow::Context;
+use anyhow::{bail, Context};
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::HashMap;
 
+mod compression;
 mod loaders;
 mod mappers;
 mod serializers;
 mod stats;
 mod writers;
 
+pub use crate::compression::Compression;
+pub use crate::loaders::servicenow::{DedupeStrategy, InputFormat};
+pub use crate::loaders::servicenow_api::{ApiOptions, Auth};
+pub use crate::mappers::balance::BalanceOptions;
+pub use crate::mappers::label::LabelMode;
+pub use crate::mappers::normalize::Normalization;
+pub use crate::mappers::output::{EntryFormat, EntryOptions, PromptField};
+pub use crate::mappers::redact::{Detector, RedactionOptions};
+pub use crate::mappers::servicenow::IncidentOrder;
+pub use crate::mappers::tokenizer::{Encoding, TokenOptions};
+pub use crate::writers::output::OutputFormat;
+pub use crate::writers::sidecar::SidecarFormat;
+
+/// Amount of tokens the price of the training refers to
+const TOKENS_PER_PRICE: f64 = 1_000_000.0;
+
 pub struct RunOptions {
     /// Print additional infos
     pub verbose: bool,
@@ -17,62 +46,350 @@ pub struct RunOptions {
     pub print_stats: bool,
     /// Trim the amount of incidents per assigment group by the given amount
     pub trim: Option<usize>,
+    /// Format of the exports. Detected by the file extension, if not set
+    pub input_format: Option<InputFormat>,
+    /// Compression of the exports. Detected by the file extension, if not set
+    pub input_compression: Option<Compression>,
+    /// Mapping between field names and the column names of CSV exports
+    pub csv_columns: HashMap<String, String>,
+    /// Strategy to resolve titles that were assigned to different groups
+    pub dedupe_strategy: DedupeStrategy,
+    /// Minimum similarity (`0` to `1`) of near-identical titles, to be de-duped as well
+    pub similarity: Option<f64>,
+    /// Order in which the incidents are processed and written
+    pub order: IncidentOrder,
+    /// Options to balance the amount of samples per assignment group
+    pub balance: BalanceOptions,
+    /// Format in which the output file has to be written
+    pub output_format: OutputFormat,
+    /// Compression of the output file(s). Detected by the file extension, if not set
+    pub output_compression: Option<Compression>,
+    /// Options to create the fine-tuning entries
+    pub entry: EntryOptions,
+    /// Filepath to a JSON file with the templates of the prompts and completions
+    pub file_templates: Option<String>,
+    /// Steps to normalize the text fields of the incidents. The normalized titles are de-duped as well
+    pub normalization: Vec<Normalization>,
+    /// Options to redact personal data from the incidents
+    pub redaction: RedactionOptions,
+    /// Options to count the tokens of the fine-tuning entries
+    pub tokens: TokenOptions,
+    /// Validate the fine-tuning entries before writing them (see [validate]). Warnings are printed, errors abort the
+    /// run
+    pub validate: bool,
+    /// Filepath to the label map, which keeps the category IDs stable between runs. It is created, if it doesn't
+    /// exist yet
+    pub file_label_map: Option<String>,
+    /// Format of the sidecar file, which links the category IDs to the assignment groups
+    pub sidecar_format: SidecarFormat,
+    /// Ratio of entries that are split off into a validation file
+    pub validation_ratio: Option<f64>,
+    /// Ratio of entries that are split off into a test file
+    pub test_ratio: Option<f64>,
+    /// Cut-off date for a chronological split. Incidents opened on or after this date are written to the test file
+    pub split_date: Option<NaiveDate>,
+    /// Seed for all randomized steps, to make the output reproducible
+    pub seed: u64,
+}
+
+/// Options for fetching the exports from the Table API
+pub struct FetchOptions {
+    /// Options to access the Table API
+    pub api: ApiOptions,
+    /// Encoded query to filter the incidents
+    pub incident_query: Option<String>,
+    /// Encoded query to filter the assignment groups
+    pub group_query: Option<String>,
+}
+
+/// Fetches the incidents and assignment groups from the Table API of an instance and writes them as JSON exports, that
+/// can be processed by [run].
+///
+/// # Arguments
+///
+/// - `file_incidents`: Filepath to where the incident export has to be written to
+/// - `file_assignment_groups`: Filepath to where the assignment groups export has to be written to
+/// - `options`: [Options][FetchOptions]
+///
+/// # Bails out when
+///
+/// - the records can't be fetched
+/// - the fetched records can't be parsed as incidents or assignment groups
+/// - the exports can't be written
+pub fn fetch(
+    file_incidents: &String,
+    file_assignment_groups: &String,
+    options: FetchOptions,
+) -> anyhow::Result<()> {
+    let verbose = options.api.verbose;
+    let client = servicenow_api::ApiClient::new(options.api)?;
+    if verbose {
+        println!("Fetching assignment groups");
+    }
+    let assignment_groups = client.fetch_table(
+        servicenow_api::TABLE_ASSIGNMENT_GROUP,
+        servicenow_api::FIELDS_ASSIGNMENT_GROUP,
+        options.group_query.as_deref(),
+    )?;
+    let assignment_groups_raw =
+        serde_json::to_string_pretty(&json!({ "result": assignment_groups }))
+            .context("Failed to serialize assignment groups")?;
+    // Make sure, that the export can be processed
+    servicenow::parse_assignment_groups(&assignment_groups_raw)
+        .context("Unable to parse fetched assignment groups")?;
+    writers::export::write_export(&assignment_groups_raw, file_assignment_groups)?;
+    if verbose {
+        println!("Fetching incidents");
+    }
+    let incidents = client.fetch_table(
+        servicenow_api::TABLE_INCIDENT,
+        servicenow_api::FIELDS_INCIDENT,
+        options.incident_query.as_deref(),
+    )?;
+    let incidents_raw = serde_json::to_string_pretty(&json!({ "records": incidents }))
+        .context("Failed to serialize incidents")?;
+    servicenow::parse_incidents(&incidents_raw).context("Unable to parse fetched incidents")?;
+    writers::export::write_export(&incidents_raw, file_incidents)?;
+    if verbose {
+        println!(
+            "{} incidents and {} assignment groups written",
+            incidents.len(),
+            assignment_groups.len()
+        );
+    }
+    Ok(())
+}
+
+/// Validates a fine-tuning file (JSON array or JSONL, optionally compressed) for common mistakes, like the
+/// `fine_tunes.prepare_data` tool of OpenAI does. The findings are printed
+///
+/// # Arguments
+///
+/// - `file_entries`: Filepath to the fine-tuning file
+///
+/// # Bails out when
+///
+/// - the file can't be loaded or parsed
+/// - the validation has found errors
+pub fn validate(file_entries: &str) -> anyhow::Result<()> {
+    let entries_raw = compression::read_to_string(file_entries, None)?;
+    let entries = loaders::fine_tuning::parse_entries(&entries_raw)
+        .with_context(|| format!("Can't parse fine-tuning file {}", file_entries))?;
+    check_entries(&entries, file_entries)
 }
 
 /// Load and processes the raw input data and writes the processed output.
 ///
 /// Following steps are done during the process:
 ///
-/// - Load and parse incidents export
-/// - De-dupe incidents by title
+/// - Load and parse the users, whose names are redacted (if configured)
+/// - Load and parse all incident exports, while de-duping incidents by internal ID and by (normalized) title
+/// - Order incidents deterministically
 /// - Load and parse assignment groups
-/// - Map input data to fine-tuning entries for OpenAI
-/// - Write the output file
+/// - Resolve references to assignment groups by name (e.g. of UI list exports)
+/// - Add assignment groups, that are only known by the references of the incidents
+/// - Load the label map (if configured)
+/// - Label the assignment groups for the completions, keeping the labels stable via the label map
+/// - Map input data to fine-tuning entries for OpenAI, by normalizing the texts and redacting personal data (if
+///   configured) and rendering the prompt and completion templates
+/// - Truncate the prompts to the token budget and count the tokens (if a vocabulary is configured)
+/// - Check, that the separator and stop sequence are consistent across all entries
+/// - Validate the entries for common mistakes (if configured)
+/// - Split the incidents chronologically into past and future incidents (if configured)
+/// - Balance the amount of (past) incidents per assignment group (if configured)
+/// - Split the entries into training, validation and test sets (if configured)
+/// - Oversample the training entries (if configured)
+/// - Count the tokens of the training entries and estimate the costs of the training (if a vocabulary is configured)
+/// - Write the output file(s)
+/// - Write the sidecar file with the labels next to the output file
+/// - Write the updated label map (if configured)
 ///
 /// # Arguments
 ///
-/// - `file_incidents`: Filepath to the incident export
+/// - `files_incidents`: Filepaths or glob patterns (e.g. `incidents_2023_*.json`) of the incident exports
 /// - `file_assignment_groups`: Filepath to the assignment groups export
 /// - `file_output`: Filepath to where the result has to be written to
 /// - `options`: [Options][RunOptions]
 ///
 /// # Bails out when
 ///
-/// - the incident export can't be loaded or parsed
+/// - the similarity threshold is invalid
+/// - an unknown field is mapped to a CSV column
+/// - an unknown field is used for the prompts
+/// - the template file can't be loaded or parsed, or a template is invalid
+/// - the users can't be loaded or parsed
+/// - a redaction rule is invalid
+/// - a token budget or price is given without vocabulary, or the vocabulary can't be loaded or parsed
+/// - a glob pattern is invalid or doesn't match any file
+/// - an incident export can't be loaded or parsed
 /// - the assignment groups can't be loaded or parsed
+/// - the label map can't be loaded, parsed or written
+/// - the assignment groups can't be labeled uniquely
+/// - a stop sequence is configured for chat entries
 /// - an incident has been assigned to an unknown assignment group
+/// - the split ratios are invalid
+/// - a chronological split is requested, but an incident has no date
+/// - the separator or stop sequence of the templates isn't consistent across all entries
+/// - the validation of the entries has found errors
 /// - the result can't be deserialized
-/// - the output-file can't be written
+/// - the output-file(s) or the sidecar file can't be written
 pub fn run(
-    file_incidents: &String,
-    file_assignment_groups: &String,
+    files_incidents: &[String],
+    file_assignment_groups: &str,
     file_output: &String,
     options: RunOptions,
 ) -> anyhow::Result<()> {
+    if let Some(similarity) = options.similarity {
+        if similarity <= 0.0 || similarity > 1.0 {
+            bail!(
+                "Invalid similarity {}. It must be within (0, 1]",
+                similarity
+            )
+        }
+    }
+    servicenow_csv::validate_columns(&options.csv_columns)?;
+    mappers::output::validate_prompt_fields(&options.entry.prompt_fields)?;
+    let templates = load_and_parse_templates(&options)?;
+    validate_token_options(&options, &templates)?;
+    let users = match &options.redaction.file_users {
+        Some(file_users) => load_and_parse_users(file_users, &options)?,
+        None => Vec::new(),
+    };
+    let processors = TextProcessors {
+        normalizer: mappers::normalize::Normalizer::new(&options.normalization),
+        redactor: mappers::redact::Redactor::new(&options.redaction, &users)?,
+        tokenizer: load_tokenizer(&options)?,
+    };
     let mut stats = Stats::new();
-    let snow_report = load_and_parse_incidents(file_incidents, &options)?;
-    let incidents_deduped = servicenow::deduped_incidents(snow_report);
+    let (mut incidents_deduped, title_conflicts) =
+        load_and_dedupe_incidents(files_incidents, &processors, &options)?;
+    mappers::servicenow::order_incidents(&mut incidents_deduped, options.order, options.seed);
     if options.verbose {
         println!("{} incidents left after de-duping", incidents_deduped.len());
+        println!(
+            "{} titles were assigned to different groups",
+            title_conflicts.len()
+        );
+        for title_conflict in &title_conflicts {
+            println!(
+                "  '{}': {}",
+                title_conflict.title,
+                title_conflict.groups.join(", ")
+            );
+        }
     }
     let mut assignment_groups: Vec<AssignmentGroup> =
         load_and_parse_assignment_groups(file_assignment_groups, &options)?;
+    let amount_resolved_names =
+        mappers::servicenow::resolve_group_names(&mut incidents_deduped, &assignment_groups);
+    if options.verbose && amount_resolved_names > 0 {
+        println!(
+            "{} incidents reference their assignment group by name",
+            amount_resolved_names
+        );
+    }
+    let amount_referenced_groups =
+        mappers::servicenow::add_referenced_groups(&incidents_deduped, &mut assignment_groups);
+    if options.verbose && amount_referenced_groups > 0 {
+        println!(
+            "{} assignment groups added from the references of the incidents",
+            amount_referenced_groups
+        );
+    }
+    mappers::servicenow::collect_title_conflicts(&title_conflicts, &assignment_groups, &mut stats);
+    let mut label_map = load_and_parse_label_map(&options)?;
     // Create map between assignment group ID and arbitrary category ID for OpenAI
     let assignment_groups_indices =
-        mappers::servicenow::map_assignment_groups(&mut assignment_groups);
-    // Map data to fine-tuning entries for OpenAI
-    let result = mappers::output::map_data(
+        mappers::servicenow::map_assignment_groups(&mut assignment_groups, &mut label_map);
+    let group_labels = mappers::label::map_group_labels(
         &incidents_deduped,
         &assignment_groups_indices,
         &assignment_groups,
-        &options.trim,
-        &mut stats,
-    )
-    .context("An error has occured during creating the mapping")?;
+        options.entry.label_mode,
+        &mut label_map,
+    )?;
+    let (incidents_past, incidents_future) = match options.split_date {
+        Some(split_date) => {
+            let (incidents_past, incidents_future) = mappers::split::split_chronologically(
+                incidents_deduped,
+                // Midnight is always a valid time
+                split_date.and_hms_opt(0, 0, 0).unwrap(),
+            )?;
+            if options.verbose {
+                println!(
+                    "{} incidents opened before and {} incidents on or after {}",
+                    incidents_past.len(),
+                    incidents_future.len(),
+                    split_date
+                );
+            }
+            (incidents_past, Some(incidents_future))
+        }
+        None => (incidents_deduped, None),
+    };
+    let incidents_past =
+        mappers::balance::balance_incidents(incidents_past, &options.balance, options.seed);
+    if options.verbose {
+        println!("{} incidents left after balancing", incidents_past.len());
+    }
+    // Map data to fine-tuning entries for OpenAI
+    let map_incidents = |incidents: &Vec<Incident>, stats: &mut Stats| {
+        mappers::output::map_data(
+            incidents,
+            &group_labels,
+            &options.trim,
+            &options.entry,
+            &templates,
+            &processors,
+            stats,
+        )
+        .context("An error has occured during creating the mapping")
+    };
+    let result = map_incidents(&incidents_past, &mut stats)?;
+    mappers::template::validate_entries(&result, &templates)?;
+    let result_future = match &incidents_future {
+        Some(incidents_future) => Some(map_incidents(incidents_future, &mut stats)?),
+        None => None,
+    };
+    if let Some(result_future) = &result_future {
+        mappers::template::validate_entries(result_future, &templates)?;
+    }
+    if options.validate {
+        check_entries(&result, "Mapped entries")?;
+        if let Some(result_future) = &result_future {
+            check_entries(result_future, "Mapped entries of the chronological split")?;
+        }
+    }
 
-    let entries_written = writers::output::write_result(&result, file_output)?;
+    let tokenizer = processors.tokenizer.as_ref();
+    write_output(
+        result,
+        result_future,
+        file_output,
+        tokenizer,
+        &mut stats,
+        &options,
+    )?;
+    let sidecar_labels = mappers::sidecar::map_sidecar_labels(
+        &assignment_groups_indices,
+        &assignment_groups,
+        &group_labels,
+        &stats,
+    );
+    let file_sidecar = writers::sidecar::sidecar_filename(file_output, options.sidecar_format);
+    writers::sidecar::write_sidecar(&sidecar_labels, &file_sidecar, options.sidecar_format)?;
     if options.verbose {
-        println!("{} entries written", entries_written);
+        println!("Labels written to {}", file_sidecar);
+    }
+    if let Some(file_label_map) = &options.file_label_map {
+        writers::label_map::write_label_map(&label_map, file_label_map)?;
+        if options.verbose {
+            println!(
+                "Label map version {} with {} labels written",
+                label_map.version,
+                label_map.labels.len()
+            );
+        }
     }
 
     if options.print_stats {
@@ -81,34 +398,236 @@ pub fn run(
     Ok(())
 }
 
-/// Loads and parses an incident export
+/// Writes the fine-tuning entries to the output file, after oversampling the training entries. If a validation or test
+/// ratio or future entries are given, the entries are split first and each data set is written to its own file (e.g.
+/// `out.train.jsonl`, `out.validation.jsonl` and `out.test.jsonl` for `out.jsonl`)
 ///
 /// # Arguments
 ///
-/// - `file_incidents`: Filepath to the incident export
+/// - `result`: The mapped fine-tuning entries
+/// - `result_future`: Entries of a chronological split, which are used as test set
+/// - `file_output`: Filepath to where the result has to be written to
+/// - `tokenizer`: Counts the tokens of the training entries, if a vocabulary has been configured
+/// - `stats`: Struct to hold mapping statistics, which receives the token counts of the training
+/// - `options`: [Options][RunOptions]
+///
+/// # Bails out when
+///
+/// - the split ratios are invalid
+/// - both, a test ratio and future entries are given
+/// - the result can't be deserialized
+/// - an output-file can't be written
+fn write_output(
+    mut result: Vec<FineTuningEntry>,
+    result_future: Option<Vec<FineTuningEntry>>,
+    file_output: &String,
+    tokenizer: Option<&Tokenizer>,
+    stats: &mut Stats,
+    options: &RunOptions,
+) -> anyhow::Result<()> {
+    if result_future.is_some() && options.test_ratio.is_some() {
+        bail!("A test ratio can't be combined with a chronological split")
+    }
+    if options.validation_ratio.is_none() && options.test_ratio.is_none() && result_future.is_none()
+    {
+        if let Some(oversample) = options.balance.oversample {
+            mappers::balance::oversample_entries(&mut result, oversample, options.seed);
+        }
+        if let Some(tokenizer) = tokenizer {
+            count_training_tokens(&result, tokenizer, stats, &options.tokens);
+        }
+        let entries_written = writers::output::write_result(
+            &result,
+            file_output,
+            options.output_format,
+            options.output_compression,
+        )?;
+        if options.verbose {
+            println!("{} entries written", entries_written);
+        }
+        return Ok(());
+    }
+    let mut split = mappers::split::split_stratified(
+        result,
+        options.validation_ratio.unwrap_or(0.0),
+        options.test_ratio.unwrap_or(0.0),
+        options.seed,
+    )?;
+    // Only oversample the training entries, to not leak duplicates into the other data sets
+    if let Some(oversample) = options.balance.oversample {
+        mappers::balance::oversample_entries(&mut split.train, oversample, options.seed);
+    }
+    if let Some(tokenizer) = tokenizer {
+        count_training_tokens(&split.train, tokenizer, stats, &options.tokens);
+    }
+    let has_test_set = options.test_ratio.is_some() || result_future.is_some();
+    if let Some(result_future) = result_future {
+        split.test = result_future;
+    }
+    let mut data_sets = vec![("train", &split.train)];
+    if options.validation_ratio.is_some() {
+        data_sets.push(("validation", &split.validation));
+    }
+    if has_test_set {
+        data_sets.push(("test", &split.test));
+    }
+    for (name, entries) in data_sets {
+        let file_data_set = writers::output::data_set_filename(file_output, name);
+        let entries_written = writers::output::write_result(
+            entries,
+            &file_data_set,
+            options.output_format,
+            options.output_compression,
+        )?;
+        if options.verbose {
+            println!("{} entries written to {}", entries_written, file_data_set);
+        }
+    }
+    Ok(())
+}
+
+/// Loads and parses the incident exports and de-dupes the incidents by internal ID and by title. The exports are read
+/// as a stream, so that only the de-duped incidents (and the internal IDs of all incidents) are kept in memory
+///
+/// # Arguments
+///
+/// - `files_incidents`: Filepaths or glob patterns of the incident exports
+/// - `processors`: Normalize and redact the titles, before they are de-duped
 /// - `options`: [Options][RunOptions]
 ///
 /// # Returns
 ///
-/// The parsed incidents
+/// Tuple with:
+///
+/// - De-duped list of incidents, in the order of the first occurrence of their titles
+/// - All titles that were assigned to different groups
 ///
 /// # Bails out when
 ///
-/// - the incident export can't be loaded or parsed
-fn load_and_parse_incidents(
-    file_incidents: &String,
+/// - a glob pattern is invalid or doesn't match any file
+/// - an incident export can't be loaded or parsed
+fn load_and_dedupe_incidents(
+    files_incidents: &[String],
+    processors: &TextProcessors,
     options: &RunOptions,
-) -> anyhow::Result<Vec<Incident>> {
-    let incidents_raw = std::fs::read_to_string(&file_incidents)
-        .with_context(|| format!("Can't read {}", &file_incidents))?;
+) -> anyhow::Result<(Vec<Incident>, Vec<TitleConflict>)> {
+    let files_incidents = expand_file_patterns(files_incidents)?;
     if options.verbose {
         println!("Parsing incidents");
     }
-    let snow_report = servicenow::parse_incidents(&incidents_raw)?;
+    let mut deduper = servicenow::IncidentDeduper::new(
+        options.dedupe_strategy,
+        &processors.normalizer,
+        &processors.redactor,
+    );
+    for file_incidents in &files_incidents {
+        let (amount_incidents, amount_duplicates) = read_incident_export(
+            file_incidents,
+            &mut deduper,
+            options.input_format,
+            options.input_compression,
+            &options.csv_columns,
+        )?;
+        if options.verbose {
+            println!(
+                "{}: {} incidents found, {} of them already in a previous export",
+                file_incidents, amount_incidents, amount_duplicates
+            );
+        }
+    }
     if options.verbose {
-        println!("{} incidents found", snow_report.len());
+        println!(
+            "{} incidents found in {} exports",
+            deduper.amount(),
+            files_incidents.len()
+        );
+    }
+    Ok(deduper.deduped(options.similarity))
+}
+
+/// Reads an incident export and adds its incidents to the de-duper
+///
+/// # Arguments
+///
+/// - `file_incidents`: Filepath of the incident export
+/// - `deduper`: De-duper, that receives the incidents
+/// - `input_format`: Format of the export. Detected by the file extension, if not set
+/// - `input_compression`: Compression of the export. Detected by the file extension, if not set
+/// - `csv_columns`: Mapping between field names and the column names of CSV exports
+///
+/// # Returns
+///
+/// Tuple with:
+///
+/// - The amount of incidents of the export
+/// - The amount of incidents, that have already been added by a previous export
+///
+/// # Bails out when
+///
+/// - the export can't be loaded or parsed
+fn read_incident_export(
+    file_incidents: &str,
+    deduper: &mut servicenow::IncidentDeduper,
+    input_format: Option<InputFormat>,
+    input_compression: Option<Compression>,
+    csv_columns: &HashMap<String, String>,
+) -> anyhow::Result<(usize, usize)> {
+    let reader = compression::open_file(file_incidents, input_compression)?;
+    let mut amount_duplicates: usize = 0;
+    let on_incident = |incident| {
+        if !deduper.add(incident) {
+            amount_duplicates += 1;
+        }
+    };
+    let amount_incidents = match servicenow::detect_input_format(file_incidents, input_format) {
+        InputFormat::Json => servicenow::read_incidents(reader, on_incident),
+        InputFormat::Csv => servicenow_csv::read_incidents_csv(reader, csv_columns, on_incident),
+        InputFormat::Xml => servicenow_xml::read_incidents_xml(reader, on_incident),
+    }
+    .with_context(|| format!("Can't parse {}", file_incidents))?;
+    Ok((amount_incidents, amount_duplicates))
+}
+
+/// Expands glob patterns to the matching filepaths. Patterns without wildcards are taken as they are, so that missing
+/// files are reported when reading them
+///
+/// # Arguments
+///
+/// - `patterns`: Filepaths or glob patterns
+///
+/// # Returns
+///
+/// The filepaths, sorted alphabetically per pattern. A file that is matched by multiple patterns is only contained once
+///
+/// # Bails out when
+///
+/// - a pattern is invalid or doesn't match any file
+fn expand_file_patterns(patterns: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut files: Vec<String> = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            if !files.contains(pattern) {
+                files.push(pattern.clone());
+            }
+            continue;
+        }
+        let paths = glob::glob(pattern).with_context(|| format!("Invalid pattern {}", pattern))?;
+        let mut has_matches = false;
+        for path in paths {
+            let path = path
+                .with_context(|| format!("Can't read {}", pattern))?
+                .to_string_lossy()
+                .to_string();
+            has_matches = true;
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        if !has_matches {
+            bail!("No files match {}", pattern)
+        }
     }
-    Ok(snow_report)
+    Ok(files)
 }
 
 /// Loads and parses the assignment groups export
@@ -126,19 +645,337 @@ fn load_and_parse_incidents(
 ///
 /// - the assignment groups can't be loaded or parsed
 fn load_and_parse_assignment_groups(
-    file_assignment_groups: &String,
+    file_assignment_groups: &str,
     options: &RunOptions,
 ) -> anyhow::Result<Vec<AssignmentGroup>> {
-    let assignment_groups_raw = std::fs::read_to_string(&file_assignment_groups)
-        .with_context(|| format!("Can't read {}", &file_assignment_groups))?;
+    let assignment_groups_raw =
+        compression::read_to_string(file_assignment_groups, options.input_compression)?;
     if options.verbose {
         println!("Parsing assignment groups");
     }
     let assignment_groups: Vec<AssignmentGroup> =
-        servicenow::parse_assignment_groups(&assignment_groups_raw)?;
+        match servicenow::detect_input_format(file_assignment_groups, options.input_format) {
+            InputFormat::Json => servicenow::parse_assignment_groups(&assignment_groups_raw)?,
+            InputFormat::Csv => servicenow_csv::parse_assignment_groups_csv(
+                &assignment_groups_raw,
+                &options.csv_columns,
+            )?,
+            InputFormat::Xml => {
+                servicenow_xml::parse_assignment_groups_xml(&assignment_groups_raw)?
+            }
+        };
     if options.verbose {
         println!("{} assignment groups found", assignment_groups.len());
         println!("Mapping data");
     }
     Ok(assignment_groups)
 }
+
+/// Validates the fine-tuning entries and prints the findings
+///
+/// # Arguments
+///
+/// - `entries`: The fine-tuning entries
+/// - `name`: Name of the entries in the printed report (e.g. the filepath)
+///
+/// # Bails out when
+///
+/// - the validation has found errors
+fn check_entries(entries: &[FineTuningEntry], name: &str) -> anyhow::Result<()> {
+    let report = mappers::validate::validate_entries(entries);
+    report.print(name);
+    let amount_errors = report.count(Severity::Error);
+    if amount_errors > 0 {
+        bail!(
+            "The validation has failed with {} error(s): {}",
+            amount_errors,
+            name
+        )
+    }
+    Ok(())
+}
+
+/// Counts the tokens of the training entries and estimates the costs of the training
+///
+/// # Arguments
+///
+/// - `entries`: The training entries
+/// - `tokenizer`: Tokenizer to count the tokens
+/// - `stats`: Struct to hold mapping statistics, which receives the token counts
+/// - `token_options`: [Options][TokenOptions] with the epochs and the price
+fn count_training_tokens(
+    entries: &[FineTuningEntry],
+    tokenizer: &Tokenizer,
+    stats: &mut Stats,
+    token_options: &TokenOptions,
+) {
+    let amount_tokens: usize = entries
+        .iter()
+        .map(|entry| tokenizer.count_entry(entry))
+        .sum();
+    stats.tokens.training = amount_tokens;
+    stats.tokens.epochs = token_options.epochs;
+    stats.tokens.training_cost = token_options
+        .price
+        .map(|price| amount_tokens as f64 * token_options.epochs as f64 * price / TOKENS_PER_PRICE);
+}
+
+/// Checks, that the options of the token counting are consistent
+///
+/// # Arguments
+///
+/// - `options`: [Options][RunOptions]
+/// - `templates`: Templates of the prompt and completion
+///
+/// # Bails out when
+///
+/// - a token budget or a price is given without vocabulary
+/// - the token budget or the amount of epochs is zero
+/// - a token budget is given, but the prompt template doesn't contain the prompt (`{{prompt}}`), which is truncated
+fn validate_token_options(options: &RunOptions, templates: &EntryTemplates) -> anyhow::Result<()> {
+    if options.tokens.file_vocabulary.is_none()
+        && (options.entry.max_prompt_tokens.is_some() || options.tokens.price.is_some())
+    {
+        bail!("Counting tokens requires a vocabulary file")
+    }
+    if options.entry.max_prompt_tokens == Some(0) {
+        bail!("The token budget of the prompts must be greater than 0")
+    }
+    if options.entry.max_prompt_tokens.is_some() && !templates.prompt.contains_variable("prompt") {
+        bail!(
+            "The token budget of the prompts requires the prompt template to contain {{{{prompt}}}}, as only the \
+            prompt fields are truncated (use --prompt-field to choose the fields)"
+        )
+    }
+    if options.tokens.epochs == 0 {
+        bail!("The amount of epochs must be greater than 0")
+    }
+    Ok(())
+}
+
+/// Loads the vocabulary (if configured) and creates the tokenizer
+///
+/// # Arguments
+///
+/// - `options`: [Options][RunOptions]
+///
+/// # Returns
+///
+/// The tokenizer. `None`, if no vocabulary has been configured
+///
+/// # Bails out when
+///
+/// - the vocabulary can't be loaded or parsed
+fn load_tokenizer(options: &RunOptions) -> anyhow::Result<Option<Tokenizer>> {
+    let file_vocabulary = match &options.tokens.file_vocabulary {
+        Some(file_vocabulary) => file_vocabulary,
+        None => return Ok(None),
+    };
+    let vocabulary_raw = compression::read_to_string(file_vocabulary, None)?;
+    let ranks = loaders::vocabulary::parse_vocabulary(&vocabulary_raw)
+        .with_context(|| format!("Can't parse vocabulary {}", file_vocabulary))?;
+    if options.verbose {
+        println!("{} tokens found in the vocabulary", ranks.len());
+    }
+    Ok(Some(Tokenizer::new(ranks, options.tokens.encoding)))
+}
+
+/// Loads and parses the users, whose names are redacted
+///
+/// # Arguments
+///
+/// - `file_users`: Filepath to the user export
+/// - `options`: [Options][RunOptions]
+///
+/// # Returns
+///
+/// The parsed users
+///
+/// # Bails out when
+///
+/// - the users can't be loaded or parsed
+fn load_and_parse_users(file_users: &str, options: &RunOptions) -> anyhow::Result<Vec<User>> {
+    let users_raw = compression::read_to_string(file_users, options.input_compression)?;
+    if options.verbose {
+        println!("Parsing users");
+    }
+    let users: Vec<User> = match servicenow::detect_input_format(file_users, options.input_format) {
+        InputFormat::Json => servicenow::parse_users(&users_raw)?,
+        InputFormat::Csv => servicenow_csv::parse_users_csv(&users_raw, &options.csv_columns)?,
+        InputFormat::Xml => servicenow_xml::parse_users_xml(&users_raw)?,
+    };
+    if options.verbose {
+        println!("{} users found", users.len());
+    }
+    Ok(users)
+}
+
+/// Loads and parses the label map, if one has been configured
+///
+/// # Arguments
+///
+/// - `options`: [Options][RunOptions]
+///
+/// # Returns
+///
+/// The parsed label map. An empty one, if no label map has been configured or the file doesn't exist yet
+///
+/// # Bails out when
+///
+/// - the label map exists, but can't be loaded or parsed
+fn load_and_parse_label_map(options: &RunOptions) -> anyhow::Result<LabelMap> {
+    let file_label_map = match &options.file_label_map {
+        Some(file_label_map) if std::path::Path::new(file_label_map).exists() => file_label_map,
+        _ => return Ok(LabelMap::new()),
+    };
+    let label_map_raw = std::fs::read_to_string(file_label_map)
+        .with_context(|| format!("Can't read {}", &file_label_map))?;
+    if options.verbose {
+        println!("Parsing label map");
+    }
+    let label_map = loaders::label_map::parse_label_map(&label_map_raw)
+        .with_context(|| format!("Can't parse label map {}", &file_label_map))?;
+    if options.verbose {
+        println!(
+            "Label map version {} with {} labels found",
+            label_map.version,
+            label_map.labels.len()
+        );
+    }
+    Ok(label_map)
+}
+
+/// Loads the template file (if configured) and parses the templates of the prompts and completions. Templates that
+/// have been passed directly take precedence over the template file
+///
+/// # Arguments
+///
+/// - `options`: [Options][RunOptions]
+///
+/// # Returns
+///
+/// The parsed templates. Missing templates fall back to the default templates of the entry format
+///
+/// # Bails out when
+///
+/// - the template file can't be loaded or parsed
+/// - a template is invalid
+fn load_and_parse_templates(options: &RunOptions) -> anyhow::Result<EntryTemplates> {
+    let template_config = match &options.file_templates {
+        Some(file_templates) => {
+            let templates_raw = std::fs::read_to_string(file_templates)
+                .with_context(|| format!("Can't read {}", &file_templates))?;
+            loaders::template::parse_template_config(&templates_raw)
+                .with_context(|| format!("Can't parse template file {}", &file_templates))?
+        }
+        None => TemplateConfig {
+            prompt: None,
+            completion: None,
+        },
+    };
+    let prompt = options
+        .entry
+        .prompt_template
+        .as_deref()
+        .or(template_config.prompt.as_deref());
+    let completion = options
+        .entry
+        .completion_template
+        .as_deref()
+        .or(template_config.completion.as_deref());
+    EntryTemplates::parse(
+        prompt,
+        completion,
+        options.entry.stop_sequence.as_deref(),
+        options.entry.format,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::normalize::Normalizer;
+    use crate::mappers::redact::{RedactionOptions, Redactor};
+    use std::path::{Path, PathBuf};
+
+    /// Creates an empty directory within the temporary directory, that is unique for the test process
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "snow_report_mapper_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_export(dir: &Path, name: &str, sys_ids: &[&str]) -> String {
+        let records: Vec<serde_json::Value> = sys_ids
+            .iter()
+            .map(|sys_id| {
+                json!({
+                    "sys_id": sys_id,
+                    "short_description": format!("Incident {}", sys_id),
+                    "assignment_group": "hardware"
+                })
+            })
+            .collect();
+        let path = dir.join(name);
+        std::fs::write(&path, json!({ "records": records }).to_string()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn expands_file_patterns() {
+        let dir = temp_dir("patterns");
+        let file_a = write_export(&dir, "a.json", &["1"]);
+        let file_b = write_export(&dir, "b.json", &["2"]);
+        let file_missing = dir.join("missing.json").to_string_lossy().to_string();
+        let pattern = dir.join("*.json").to_string_lossy().to_string();
+        // Literal filepaths are kept, even if they are missing
+        assert_eq!(
+            expand_file_patterns(std::slice::from_ref(&file_missing)).unwrap(),
+            [file_missing]
+        );
+        // Files that are matched by multiple patterns are only contained once
+        assert_eq!(
+            expand_file_patterns(&[file_b.clone(), pattern.clone(), file_a.clone()]).unwrap(),
+            [file_b.clone(), file_a.clone()]
+        );
+        let pattern_no_matches = dir.join("*.csv").to_string_lossy().to_string();
+        let error = expand_file_patterns(&[pattern, pattern_no_matches.clone()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("No files match {}", pattern_no_matches)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn counts_incidents_of_previous_exports() {
+        let dir = temp_dir("previous_exports");
+        let file_a = write_export(&dir, "a.json", &["1", "2"]);
+        let file_b = write_export(&dir, "b.json", &["2", "3", "1"]);
+        let normalizer = Normalizer::new(&[]);
+        let options = RedactionOptions {
+            detectors: Vec::new(),
+            rules: Vec::new(),
+            file_users: None,
+        };
+        let redactor = Redactor::new(&options, &[]).unwrap();
+        let mut deduper =
+            servicenow::IncidentDeduper::new(DedupeStrategy::Last, &normalizer, &redactor);
+        let columns = HashMap::new();
+        assert_eq!(
+            read_incident_export(&file_a, &mut deduper, None, None, &columns).unwrap(),
+            (2, 0)
+        );
+        assert_eq!(
+            read_incident_export(&file_b, &mut deduper, None, None, &columns).unwrap(),
+            (3, 2)
+        );
+        assert_eq!(deduper.deduped(None).0.len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+/// Loader for fine-tuning files, as written by this crate (or any other tool)
+use crate::mappers::output::FineTuningEntry;
+use anyhow::{Context, Result};
+
+/// Parses a fine-tuning file. Both formats are supported: a JSON array of all entries and one JSON object per line
+/// (JSONL). The format is detected by the first character of the content
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Returns
+///
+/// The fine-tuning entries
+///
+/// # Bails out when
+///
+/// - the content isn't a JSON array of fine-tuning entries
+/// - a line of a JSONL file isn't a fine-tuning entry
+pub fn parse_entries(input_raw: &str) -> Result<Vec<FineTuningEntry>> {
+    if input_raw.trim_start().starts_with('[') {
+        return serde_json::from_str(input_raw).context("Unable to parse file");
+    }
+    let mut entries: Vec<FineTuningEntry> = Vec::new();
+    for (idx, line) in input_raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: FineTuningEntry = serde_json::from_str(line).with_context(|| {
+            format!(
+                "Line {} isn't a fine-tuning entry (`prompt` and `completion` or `messages`)",
+                idx + 1
+            )
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(entries: &[FineTuningEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.label()).collect()
+    }
+
+    #[test]
+    fn parses_json_arrays() {
+        let entries = parse_entries(
+            r#"
+            [
+                {"prompt": "Printer jammed\n\n###\n\n", "completion": " 1"},
+                {"messages": [{"role": "user", "content": "VPN down"}, {"role": "assistant", "content": "2"}]}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(labels(&entries), [" 1", "2"]);
+    }
+
+    #[test]
+    fn parses_jsonl() {
+        let entries = parse_entries(
+            r#"{"prompt": "Printer jammed", "completion": " 1"}
+
+{"prompt": "[VPN] down", "completion": " 2"}
+"#,
+        )
+        .unwrap();
+        assert_eq!(labels(&entries), [" 1", " 2"]);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let error = parse_entries(
+            "{\"prompt\": \"Printer jammed\", \"completion\": \" 1\"}\n{\"prompt\": \"VPN down\"}",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 2 isn't a fine-tuning entry (`prompt` and `completion` or `messages`)"
+        );
+    }
+}
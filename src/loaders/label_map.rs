@@ -0,0 +1,67 @@
+/// Loader and structs for the persistent label map, which keeps the category IDs stable between runs
+use crate::serializers::naive_datetime;
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Maps SNOW assignment groups to the category IDs, that are used for fine-tuning.
+///
+/// Labels are only ever appended. Labels of groups that don't exist anymore are kept, so that their category IDs are
+/// never reused.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelMap {
+    /// Version of the label map. It is increased every time new labels are added
+    pub version: u32,
+    /// Category ID that will be assigned to the next new assignment group
+    pub next_category: usize,
+    /// All labels that have ever been assigned
+    pub labels: Vec<Label>,
+}
+
+/// A single label, linking an assignment group to its category ID
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Label {
+    /// Category ID that is used for fine-tuning
+    pub category: usize,
+    /// The internal ID of the assignment group within SNOW
+    pub sys_id: String,
+    /// The last known name of the assignment group
+    pub name: String,
+    /// The date when the assignment group has been added to the label map
+    #[serde(with = "naive_datetime")]
+    pub first_seen: NaiveDateTime,
+    /// Labels of the assignment group that are used in the completions, by label mode (e.g. `slug`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub completion_labels: BTreeMap<String, String>,
+}
+
+impl LabelMap {
+    pub fn new() -> Self {
+        LabelMap {
+            version: 0,
+            next_category: 0,
+            labels: Vec::new(),
+        }
+    }
+}
+
+impl Default for LabelMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a label map
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Bails out when
+///
+/// - the content can't be deserialized to a `LabelMap`
+pub fn parse_label_map(input_raw: &str) -> Result<LabelMap> {
+    let label_map: LabelMap = serde_json::from_str(input_raw).context("Unable to parse file")?;
+    Ok(label_map)
+}
@@ -1,2 +1,9 @@
 /// Modules for data loaders
+pub mod fine_tuning;
+pub mod label_map;
 pub mod servicenow;
+pub mod servicenow_api;
+pub mod servicenow_csv;
+pub mod servicenow_xml;
+pub mod template;
+pub mod vocabulary;
@@ -1,9 +1,30 @@
 /// Loaders and structs for SNOW (ServiceNow) data exports
-use crate::serializers::naive_datetime;
+use crate::compression::split_compression_extension;
+use crate::mappers::normalize::Normalizer;
+use crate::mappers::redact::Redactor;
+use crate::mappers::similarity::cluster_titles;
+use crate::serializers::field_value::{self, FieldValue};
+use crate::serializers::{naive_datetime, optional_naive_datetime};
 use anyhow::{Context, Result};
 use chrono::NaiveDateTime;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserializer;
 use serde_derive::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// Format of an export
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    /// JSON export
+    Json,
+    /// CSV export with a header row
+    Csv,
+    /// XML unload file
+    Xml,
+}
 
 /// Represents an export of a list of incidents
 #[derive(Deserialize)]
@@ -18,25 +39,261 @@ pub struct AssignmentGroupExport {
     pub result: Vec<AssignmentGroup>,
 }
 
+/// Represents an export of users (`sys_user`)
+#[derive(Deserialize)]
+pub struct UserExport {
+    pub result: Vec<User>,
+}
+
+/// A single SNOW user. Only the fields that identify a person are read
+#[derive(Deserialize, Debug)]
+pub struct User {
+    /// Full name of the user (e.g. `John Meier`)
+    #[serde(default, deserialize_with = "field_value::optional_display_value")]
+    pub name: Option<String>,
+    /// Login ID of the user (e.g. `jmeier`)
+    #[serde(default, deserialize_with = "field_value::optional_display_value")]
+    pub user_name: Option<String>,
+    /// Email address of the user
+    #[serde(default, deserialize_with = "field_value::optional_display_value")]
+    pub email: Option<String>,
+}
+
 /// A single SNOW assignment group
 #[derive(Deserialize, Debug)]
 pub struct AssignmentGroup {
     /// The internal ID an an assignment group within SNOW
+    #[serde(deserialize_with = "field_value::value")]
     pub sys_id: String,
     /// The name of the assignment group
+    #[serde(deserialize_with = "field_value::display_value")]
     pub name: String,
     /// The date when the assignment group was created
     #[serde(with = "naive_datetime")]
     pub sys_created_on: NaiveDateTime,
 }
 
+/// Strategy to resolve titles that were assigned to different groups during de-duping
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupeStrategy {
+    /// Keep the incident that occurs last in the export
+    Last,
+    /// Keep the group the title has been assigned to most often
+    Majority,
+    /// Keep the most recent assignment
+    MostRecent,
+    /// Drop titles that were assigned to different groups entirely
+    Drop,
+}
+
+/// A title that was assigned to different groups
+#[derive(Debug)]
+pub struct TitleConflict {
+    /// Title of the incidents
+    pub title: String,
+    /// Groups (internal IDs) to which the title was assigned to, sorted ascending
+    pub groups: Vec<String>,
+}
+
+/// Fields of an incident that contain text, which can be used to create the prompts
+pub const TEXT_FIELDS: [&str; 8] = [
+    "number",
+    "short_description",
+    "description",
+    "category",
+    "subcategory",
+    "cmdb_ci",
+    "business_service",
+    "location",
+];
+
 /// A single SNOW incident
 #[derive(Deserialize, Clone)]
+#[serde(from = "IncidentRecord")]
 pub struct Incident {
+    /// The internal ID of the incident within SNOW, if the export contains it
+    pub sys_id: Option<String>,
+    /// Number of the incident (e.g. `INC0012345`)
+    pub number: Option<String>,
     /// Title of the ServiceNOW incident
     pub short_description: String,
+    /// Detailed description of the incident
+    pub description: Option<String>,
+    /// Category of the incident
+    pub category: Option<String>,
+    /// Subcategory of the incident
+    pub subcategory: Option<String>,
+    /// Affected configuration item (its name, if the export contains it)
+    pub cmdb_ci: Option<String>,
+    /// Affected business service (its name, if the export contains it)
+    pub business_service: Option<String>,
+    /// Location of the caller (its name, if the export contains it)
+    pub location: Option<String>,
     /// Group (internal ID) to which the incident was assigned to
     pub assignment_group: String,
+    /// Name of the group to which the incident was assigned to, if the export contains it
+    pub assignment_group_name: Option<String>,
+    /// The date when the incident was opened
+    pub opened_at: Option<NaiveDateTime>,
+    /// The date when the incident was created
+    pub sys_created_on: Option<NaiveDateTime>,
+}
+
+/// A single SNOW incident, as it is represented in the exports. Each field is either a plain value or a value object of
+/// the Table API (see [FieldValue])
+#[derive(Deserialize)]
+struct IncidentRecord {
+    #[serde(default, deserialize_with = "field_value::optional_value")]
+    sys_id: Option<String>,
+    #[serde(default, deserialize_with = "field_value::optional_display_value")]
+    number: Option<String>,
+    #[serde(deserialize_with = "field_value::display_value")]
+    short_description: String,
+    #[serde(default)]
+    description: Option<FieldValue>,
+    #[serde(default)]
+    category: Option<FieldValue>,
+    #[serde(default)]
+    subcategory: Option<FieldValue>,
+    #[serde(default)]
+    cmdb_ci: Option<FieldValue>,
+    #[serde(default, rename = "cmdb_ci.display_value", alias = "cmdb_ci.name")]
+    cmdb_ci_name: Option<String>,
+    #[serde(default)]
+    business_service: Option<FieldValue>,
+    #[serde(
+        default,
+        rename = "business_service.display_value",
+        alias = "business_service.name"
+    )]
+    business_service_name: Option<String>,
+    #[serde(default)]
+    location: Option<FieldValue>,
+    #[serde(default, rename = "location.display_value", alias = "location.name")]
+    location_name: Option<String>,
+    assignment_group: FieldValue,
+    /// Display value of the assigned group, as provided by XML unload files (or the dot-walked name of the Table API)
+    #[serde(
+        default,
+        rename = "assignment_group.display_value",
+        alias = "assignment_group.name"
+    )]
+    assignment_group_name: Option<String>,
+    #[serde(default, with = "optional_naive_datetime")]
+    opened_at: Option<NaiveDateTime>,
+    #[serde(default, with = "optional_naive_datetime")]
+    sys_created_on: Option<NaiveDateTime>,
+}
+
+impl From<IncidentRecord> for Incident {
+    fn from(record: IncidentRecord) -> Self {
+        let (assignment_group, display_value) = match record.assignment_group {
+            FieldValue::Plain(id) => (id, None),
+            FieldValue::Object {
+                value,
+                display_value,
+            } => (value.unwrap_or_default(), display_value),
+        };
+        Incident {
+            sys_id: record.sys_id.filter(|sys_id| !sys_id.is_empty()),
+            number: record.number,
+            short_description: record.short_description,
+            description: readable_value(record.description, None),
+            category: readable_value(record.category, None),
+            subcategory: readable_value(record.subcategory, None),
+            cmdb_ci: readable_value(record.cmdb_ci, record.cmdb_ci_name),
+            business_service: readable_value(record.business_service, record.business_service_name),
+            location: readable_value(record.location, record.location_name),
+            assignment_group,
+            assignment_group_name: display_value
+                .or(record.assignment_group_name)
+                .filter(|name| !name.is_empty()),
+            opened_at: record.opened_at,
+            sys_created_on: record.sys_created_on,
+        }
+    }
+}
+
+/// Gets the human readable value of a field
+///
+/// # Arguments
+///
+/// - `field`: The field
+/// - `display_value`: Display value of the field, that has been provided separately (e.g. by a dot-walked field)
+///
+/// # Returns
+///
+/// The display value, falling back to the value of the field. `None` if both are missing or empty
+fn readable_value(field: Option<FieldValue>, display_value: Option<String>) -> Option<String> {
+    let (value, field_display_value) = match field {
+        Some(FieldValue::Plain(value)) => (Some(value), None),
+        Some(FieldValue::Object {
+            value,
+            display_value,
+        }) => (value, display_value),
+        None => (None, None),
+    };
+    [field_display_value, display_value, value]
+        .into_iter()
+        .flatten()
+        .find(|value| !value.is_empty())
+}
+
+impl Incident {
+    /// Gets the date when the incident was opened. Falls back to the creation date, if the open date is missing
+    ///
+    /// # Returns
+    ///
+    /// The open date of the incident, if there is any
+    pub fn opened(&self) -> Option<NaiveDateTime> {
+        self.opened_at.or(self.sys_created_on)
+    }
+
+    /// Gets the value of a text field (see [TEXT_FIELDS])
+    ///
+    /// # Arguments
+    ///
+    /// - `field`: Name of the field
+    ///
+    /// # Returns
+    ///
+    /// The value of the field. `None` if the incident has no value for it or the field is unknown
+    pub fn text_field(&self, field: &str) -> Option<&str> {
+        match field {
+            "number" => self.number.as_deref(),
+            "short_description" => Some(&self.short_description),
+            "description" => self.description.as_deref(),
+            "category" => self.category.as_deref(),
+            "subcategory" => self.subcategory.as_deref(),
+            "cmdb_ci" => self.cmdb_ci.as_deref(),
+            "business_service" => self.business_service.as_deref(),
+            "location" => self.location.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of a text field (see [TEXT_FIELDS]) for modification
+    ///
+    /// # Arguments
+    ///
+    /// - `field`: Name of the field
+    ///
+    /// # Returns
+    ///
+    /// The value of the field. `None` if the incident has no value for it or the field is unknown
+    pub fn text_field_mut(&mut self, field: &str) -> Option<&mut String> {
+        match field {
+            "number" => self.number.as_mut(),
+            "short_description" => Some(&mut self.short_description),
+            "description" => self.description.as_mut(),
+            "category" => self.category.as_mut(),
+            "subcategory" => self.subcategory.as_mut(),
+            "cmdb_ci" => self.cmdb_ci.as_mut(),
+            "business_service" => self.business_service.as_mut(),
+            "location" => self.location.as_mut(),
+            _ => None,
+        }
+    }
 }
 
 /// Parses an export of SNOW incidents and de-dupes entries (based on the title)
@@ -54,6 +311,89 @@ pub fn parse_incidents(input_raw: &str) -> Result<Vec<Incident>> {
     Ok(incidents.records)
 }
 
+/// Reads an export of SNOW incidents, one incident at a time. Only the current incident is kept in memory, so that
+/// exports of any size can be read.
+///
+/// # Arguments
+///
+/// - `reader`: Reader of the export (should be buffered)
+/// - `on_incident`: Called with each incident, in the order of the export
+///
+/// # Returns
+///
+/// The amount of incidents that have been read
+///
+/// # Bails out when
+///
+/// - the content can't be deserialized to `Incident`s
+pub fn read_incidents<R: Read>(reader: R, on_incident: impl FnMut(Incident)) -> Result<usize> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let amount = deserializer
+        .deserialize_map(IncidentExportVisitor { on_incident })
+        .context("Unable to parse file")?;
+    deserializer.end().context("Unable to parse file")?;
+    Ok(amount)
+}
+
+/// Visits an export of incidents, passing on the records one at a time instead of collecting them
+struct IncidentExportVisitor<F> {
+    /// Called with each incident
+    on_incident: F,
+}
+
+impl<'de, F: FnMut(Incident)> Visitor<'de> for IncidentExportVisitor<F> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an incident export with a list of records")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<usize, A::Error> {
+        let mut amount: Option<usize> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "records" {
+                amount = Some(map.next_value_seed(RecordsVisitor {
+                    on_incident: &mut self.on_incident,
+                })?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        amount.ok_or_else(|| de::Error::missing_field("records"))
+    }
+}
+
+/// Visits the records of an export of incidents
+struct RecordsVisitor<'a, F> {
+    /// Called with each incident
+    on_incident: &'a mut F,
+}
+
+impl<'de, F: FnMut(Incident)> DeserializeSeed<'de> for RecordsVisitor<'_, F> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(Incident)> Visitor<'de> for RecordsVisitor<'_, F> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of incidents")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut amount: usize = 0;
+        while let Some(incident) = seq.next_element::<Incident>()? {
+            (self.on_incident)(incident);
+            amount += 1;
+        }
+        Ok(amount)
+    }
+}
+
 /// Parses an export of assignment groups
 ///
 /// # Arguments
@@ -69,26 +409,574 @@ pub fn parse_assignment_groups(input_raw: &str) -> Result<Vec<AssignmentGroup>>
     Ok(incidents.result)
 }
 
-/// De-dupes incidents with duplicate incident titles (to also prevent to have titles assigned
-/// to different groups)
+/// Parses an export of users
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Bails out when
+///
+/// - the content can't be deserialized to `User`s
+pub fn parse_users(input_raw: &str) -> Result<Vec<User>> {
+    let users: UserExport = serde_json::from_str(input_raw).context("Unable to parse file")?;
+    Ok(users.result)
+}
+
+/// Determines the format of an export
 ///
 /// # Arguments
 ///
-/// - `incidents`: All incidents
+/// - `filename`: Filepath of the export
+/// - `format`: Explicitly configured format, which takes precedence
 ///
 /// # Returns
 ///
-/// De-duped list of incidents
-pub fn deduped_incidents(incidents: Vec<Incident>) -> Vec<Incident> {
-    // De-duping incidents with duplicate incident titles (to also prevent to have titles assigned
-    // to different groups)
-    let mut deduped_map: HashMap<String, Incident> = HashMap::new();
-    for incident in incidents {
-        deduped_map.insert(incident.short_description.clone(), incident);
+/// The configured format or the format, that matches the file extension (ignoring a compression extension, like
+/// `.gz`). Falls back to JSON
+pub fn detect_input_format(filename: &str, format: Option<InputFormat>) -> InputFormat {
+    if let Some(format) = format {
+        return format;
     }
-    let mut deduped_incidents: Vec<Incident> = Vec::new();
-    for incident in deduped_map.values() {
-        deduped_incidents.push((*incident).clone());
+    let (filename, _) = split_compression_extension(filename);
+    let extension = Path::new(filename)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => InputFormat::Csv,
+        Some("xml") => InputFormat::Xml,
+        _ => InputFormat::Json,
+    }
+}
+
+/// Collects incidents while they are being parsed and de-dupes them by title (to also prevent to have titles assigned
+/// to different groups).
+///
+/// Instead of keeping all incidents, only the incident that represents a group is kept for each title and group. This
+/// way, the memory mostly depends on the amount of distinct titles, rather than on the size of the export.
+///
+/// Incidents that occur multiple times (e.g. in overlapping exports) are recognized by their internal ID and only added
+/// once. The internal IDs of all incidents are kept for this, so they still grow with the size of the export.
+///
+/// Titles are compared after they have been normalized and redacted, so that e.g. `RE: Printer broken` and `Printer
+/// broken` are duplicates, if prefixes are stripped. The same goes for `Call back John Meier` and `Call back Anna
+/// Keller`, if both names are redacted, as they are mapped to the same prompt.
+pub struct IncidentDeduper<'a> {
+    /// Strategy to resolve titles that were assigned to different groups
+    strategy: DedupeStrategy,
+    /// Normalizes the titles, before they are compared
+    normalizer: &'a Normalizer,
+    /// Redacts the titles, before they are compared
+    redactor: &'a Redactor,
+    /// Internal IDs of all incidents that have been added
+    sys_ids: HashSet<String>,
+    /// Index of the candidates of each title within `duplicates`
+    title_indices: HashMap<String, usize>,
+    /// Candidates of each title, in the order of the first occurrence of their titles
+    duplicates: Vec<TitleCandidates>,
+    /// Amount of incidents that have been added
+    amount: usize,
+}
+
+/// Candidates of all incidents with the same (normalized) title
+struct TitleCandidates {
+    /// Normalized title of the incidents
+    title: String,
+    /// Candidate of each group (internal ID) the title was assigned to
+    groups: BTreeMap<String, Candidate>,
+}
+
+/// The incident that represents all incidents with the same title and group
+struct Candidate {
+    /// Amount of incidents with the title and group
+    votes: usize,
+    /// Rank of the incident. The incident with the highest rank is kept
+    rank: CandidateRank,
+    /// The representing incident
+    incident: Incident,
+}
+
+/// Rank of a candidate: the open date (only for strategies that consider it) and the position within the export
+type CandidateRank = (Option<NaiveDateTime>, usize);
+
+impl<'a> IncidentDeduper<'a> {
+    /// Creates an empty de-duper
+    ///
+    /// # Arguments
+    ///
+    /// - `strategy`: Strategy to resolve titles that were assigned to different groups
+    /// - `normalizer`: Normalizes the titles, before they are compared
+    /// - `redactor`: Redacts the normalized titles, before they are compared
+    pub fn new(
+        strategy: DedupeStrategy,
+        normalizer: &'a Normalizer,
+        redactor: &'a Redactor,
+    ) -> Self {
+        IncidentDeduper {
+            strategy,
+            normalizer,
+            redactor,
+            sys_ids: HashSet::new(),
+            title_indices: HashMap::new(),
+            duplicates: Vec::new(),
+            amount: 0,
+        }
+    }
+
+    /// Adds the next incident of the export
+    ///
+    /// # Arguments
+    ///
+    /// - `incident`: The incident
+    ///
+    /// # Returns
+    ///
+    /// `false`, if an incident with the same internal ID has already been added. The incident is skipped then
+    pub fn add(&mut self, incident: Incident) -> bool {
+        if let Some(sys_id) = &incident.sys_id {
+            if !self.sys_ids.insert(sys_id.clone()) {
+                return false;
+            }
+        }
+        let position = self.amount;
+        self.amount += 1;
+        // Incidents without date are considered older than all others, the order of the export decides between
+        // incidents with the same date
+        let rank = match self.strategy {
+            DedupeStrategy::Last | DedupeStrategy::Drop => (None, position),
+            DedupeStrategy::MostRecent | DedupeStrategy::Majority => (incident.opened(), position),
+        };
+        let normalized = self.normalizer.normalize(&incident.short_description);
+        let title = self.redactor.redact_uncounted(&normalized);
+        let idx = match self.title_indices.get(title.as_ref()) {
+            Some(idx) => *idx,
+            None => {
+                self.title_indices
+                    .insert(title.to_string(), self.duplicates.len());
+                self.duplicates.push(TitleCandidates {
+                    title: title.into_owned(),
+                    groups: BTreeMap::new(),
+                });
+                self.duplicates.len() - 1
+            }
+        };
+        let groups = &mut self.duplicates[idx].groups;
+        match groups.get_mut(&incident.assignment_group) {
+            Some(candidate) => {
+                candidate.votes += 1;
+                if rank >= candidate.rank {
+                    candidate.rank = rank;
+                    candidate.incident = incident;
+                }
+            }
+            None => {
+                groups.insert(
+                    incident.assignment_group.clone(),
+                    Candidate {
+                        votes: 1,
+                        rank,
+                        incident,
+                    },
+                );
+            }
+        }
+        true
+    }
+
+    /// Gets the amount of incidents that have been added
+    ///
+    /// # Returns
+    ///
+    /// The amount of incidents, including duplicate titles
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Resolves the duplicates of all added incidents.
+    ///
+    /// If the same title has been assigned to different groups, the conflict is resolved according to the strategy.
+    /// With a similarity threshold, near-identical titles (see [cluster_titles]) are treated as duplicates as well.
+    ///
+    /// # Arguments
+    ///
+    /// - `similarity_threshold`: Minimum similarity (`0` to `1`) of near-identical titles, to be considered duplicates
+    ///
+    /// # Returns
+    ///
+    /// Tuple with:
+    ///
+    /// - De-duped list of incidents, in the order of the first occurrence of their titles
+    /// - All titles that were assigned to different groups
+    pub fn deduped(self, similarity_threshold: Option<f64>) -> (Vec<Incident>, Vec<TitleConflict>) {
+        let strategy = self.strategy;
+        let mut duplicates = self.duplicates;
+        if let Some(similarity_threshold) = similarity_threshold {
+            duplicates = merge_similar_titles(duplicates, similarity_threshold);
+        }
+        let mut deduped_incidents: Vec<Incident> = Vec::new();
+        let mut conflicts: Vec<TitleConflict> = Vec::new();
+        for candidates in duplicates {
+            if candidates.groups.len() > 1 {
+                conflicts.push(TitleConflict {
+                    title: candidates.title,
+                    groups: candidates.groups.keys().cloned().collect(),
+                });
+                if strategy == DedupeStrategy::Drop {
+                    continue;
+                }
+            }
+            if let Some(incident) = resolve_duplicates(candidates.groups, strategy) {
+                deduped_incidents.push(incident);
+            }
+        }
+        (deduped_incidents, conflicts)
+    }
+}
+
+/// Merges the candidates of near-identical titles
+///
+/// # Arguments
+///
+/// - `duplicates`: Candidates, grouped by their (exact) title
+/// - `similarity_threshold`: Minimum similarity (`0` to `1`) of near-identical titles
+///
+/// # Returns
+///
+/// Candidates, grouped by near-identical titles, in the order of the first occurrence of their titles
+fn merge_similar_titles(
+    duplicates: Vec<TitleCandidates>,
+    similarity_threshold: f64,
+) -> Vec<TitleCandidates> {
+    let titles: Vec<&str> = duplicates
+        .iter()
+        .map(|candidates| candidates.title.as_str())
+        .collect();
+    let clusters = cluster_titles(&titles, similarity_threshold);
+    let mut merged: Vec<TitleCandidates> = Vec::new();
+    let mut cluster_indices: HashMap<usize, usize> = HashMap::new();
+    for (cluster, candidates) in clusters.into_iter().zip(duplicates) {
+        let idx = match cluster_indices.get(&cluster) {
+            Some(idx) => *idx,
+            None => {
+                cluster_indices.insert(cluster, merged.len());
+                merged.push(candidates);
+                continue;
+            }
+        };
+        let groups = &mut merged[idx].groups;
+        for (group, candidate) in candidates.groups {
+            match groups.get_mut(&group) {
+                Some(existing) => {
+                    existing.votes += candidate.votes;
+                    if candidate.rank > existing.rank {
+                        existing.rank = candidate.rank;
+                        existing.incident = candidate.incident;
+                    }
+                }
+                None => {
+                    groups.insert(group, candidate);
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Picks the incident that represents all incidents with the same title
+///
+/// # Arguments
+///
+/// - `groups`: Candidate of each group the title was assigned to
+/// - `strategy`: Strategy to resolve titles that were assigned to different groups
+///
+/// # Returns
+///
+/// The representing incident
+fn resolve_duplicates(
+    groups: BTreeMap<String, Candidate>,
+    strategy: DedupeStrategy,
+) -> Option<Incident> {
+    let candidate = match strategy {
+        // The rank already considers the open date, if the strategy requires it
+        DedupeStrategy::Last | DedupeStrategy::Drop | DedupeStrategy::MostRecent => {
+            groups.into_values().max_by_key(|candidate| candidate.rank)
+        }
+        // Ties are resolved by the most recent assignment
+        DedupeStrategy::Majority => groups
+            .into_values()
+            .max_by_key(|candidate| (candidate.votes, candidate.rank)),
+    };
+    candidate.map(|candidate| candidate.incident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::redact::RedactionOptions;
+
+    /// Export of incidents, as fetched from the Table API with `sysparm_display_value=all`
+    const INCIDENTS_DISPLAY_VALUE_ALL: &str =
+        include_str!("../../tests/fixtures/incidents_display_value_all.json");
+    /// Export of assignment groups, as fetched from the Table API with `sysparm_display_value=all`
+    const GROUPS_DISPLAY_VALUE_ALL: &str =
+        include_str!("../../tests/fixtures/groups_display_value_all.json");
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn incident(sys_id: &str, title: &str, group: &str, opened_at: Option<&str>) -> Incident {
+        Incident {
+            sys_id: Some(sys_id.to_string()),
+            number: None,
+            short_description: title.to_string(),
+            description: None,
+            category: None,
+            subcategory: None,
+            cmdb_ci: None,
+            business_service: None,
+            location: None,
+            assignment_group: group.to_string(),
+            assignment_group_name: None,
+            opened_at: opened_at.map(datetime),
+            sys_created_on: None,
+        }
+    }
+
+    #[test]
+    fn parses_incidents_with_display_values() {
+        let incidents = parse_incidents(INCIDENTS_DISPLAY_VALUE_ALL).unwrap();
+        assert_eq!(incidents.len(), 2);
+        let incident = &incidents[0];
+        assert_eq!(
+            incident.sys_id.as_deref(),
+            Some("9d385017c611228701d22104cc95c371")
+        );
+        assert_eq!(incident.number.as_deref(), Some("INC0010001"));
+        assert_eq!(incident.short_description, "Printer on floor 3 is jammed");
+        assert_eq!(
+            incident.description.as_deref(),
+            Some("Paper is stuck in tray 2")
+        );
+        assert_eq!(incident.category.as_deref(), Some("Hardware"));
+        assert_eq!(incident.subcategory, None);
+        assert_eq!(incident.cmdb_ci.as_deref(), Some("PRN-FL3-01"));
+        assert_eq!(incident.business_service, None);
+        assert_eq!(incident.location.as_deref(), Some("Zurich"));
+        assert_eq!(
+            incident.assignment_group,
+            "8a5055c9c61122780043563ef53438e3"
+        );
+        assert_eq!(incident.assignment_group_name.as_deref(), Some("Hardware"));
+        assert_eq!(incident.opened_at, Some(datetime("2023-01-02 08:15:00")));
+        assert_eq!(
+            incident.sys_created_on,
+            Some(datetime("2023-01-02 08:16:12"))
+        );
+        let incident = &incidents[1];
+        assert_eq!(incident.opened_at, None);
+        assert_eq!(incident.opened(), Some(datetime("2023-01-03 13:02:45")));
+        assert_eq!(incident.business_service.as_deref(), Some("Remote Access"));
+    }
+
+    #[test]
+    fn reads_incidents_with_display_values() {
+        let mut numbers: Vec<String> = Vec::new();
+        let amount = read_incidents(INCIDENTS_DISPLAY_VALUE_ALL.as_bytes(), |incident| {
+            numbers.extend(incident.number)
+        })
+        .unwrap();
+        assert_eq!(amount, 2);
+        assert_eq!(numbers, ["INC0010001", "INC0010002"]);
+    }
+
+    #[test]
+    fn parses_assignment_groups_with_display_values() {
+        let groups = parse_assignment_groups(GROUPS_DISPLAY_VALUE_ALL).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].sys_id, "8a5055c9c61122780043563ef53438e3");
+        assert_eq!(groups[0].name, "Hardware");
+        assert_eq!(groups[0].sys_created_on, datetime("2022-02-18 10:20:03"));
+    }
+
+    #[test]
+    fn parses_plain_values() {
+        let incidents = parse_incidents(
+            r#"{"records": [{"sys_id": "1", "number": "INC1", "short_description": "Title",
+                "assignment_group": "g1", "opened_at": "2023-01-02 08:15:00"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(incidents[0].sys_id.as_deref(), Some("1"));
+        assert_eq!(incidents[0].short_description, "Title");
+        assert_eq!(incidents[0].assignment_group, "g1");
+        assert_eq!(incidents[0].assignment_group_name, None);
+        assert_eq!(
+            incidents[0].opened_at,
+            Some(datetime("2023-01-02 08:15:00"))
+        );
+    }
+
+    fn redactor() -> Redactor {
+        let options = RedactionOptions {
+            detectors: Vec::new(),
+            rules: Vec::new(),
+            file_users: None,
+        };
+        Redactor::new(&options, &[]).unwrap()
+    }
+
+    fn sys_ids(incidents: &[Incident]) -> Vec<&str> {
+        incidents
+            .iter()
+            .filter_map(|incident| incident.sys_id.as_deref())
+            .collect()
+    }
+
+    fn dedupe(
+        strategy: DedupeStrategy,
+        incidents: Vec<Incident>,
+    ) -> (Vec<Incident>, Vec<TitleConflict>) {
+        let normalizer = Normalizer::new(&[]);
+        let redactor = redactor();
+        let mut deduper = IncidentDeduper::new(strategy, &normalizer, &redactor);
+        for incident in incidents {
+            deduper.add(incident);
+        }
+        deduper.deduped(None)
+    }
+
+    /// Incidents with the same title, that were assigned to two groups
+    fn conflicting_incidents() -> Vec<Incident> {
+        vec![
+            incident(
+                "1",
+                "Printer jammed",
+                "network",
+                Some("2023-01-03 08:00:00"),
+            ),
+            incident(
+                "2",
+                "Printer jammed",
+                "hardware",
+                Some("2023-01-01 08:00:00"),
+            ),
+            incident(
+                "3",
+                "Printer jammed",
+                "hardware",
+                Some("2023-01-02 08:00:00"),
+            ),
+            incident("4", "VPN down", "network", None),
+        ]
+    }
+
+    #[test]
+    fn keeps_last_assignment() {
+        let (incidents, conflicts) = dedupe(DedupeStrategy::Last, conflicting_incidents());
+        assert_eq!(sys_ids(&incidents), ["3", "4"]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].title, "Printer jammed");
+        assert_eq!(conflicts[0].groups, ["hardware", "network"]);
+    }
+
+    #[test]
+    fn keeps_majority_assignment() {
+        let (incidents, conflicts) = dedupe(DedupeStrategy::Majority, conflicting_incidents());
+        assert_eq!(sys_ids(&incidents), ["3", "4"]);
+        assert_eq!(conflicts.len(), 1);
+        // Ties are resolved by the most recent assignment
+        let mut incidents = conflicting_incidents();
+        incidents.remove(1);
+        let (incidents, _) = dedupe(DedupeStrategy::Majority, incidents);
+        assert_eq!(sys_ids(&incidents), ["1", "4"]);
+    }
+
+    #[test]
+    fn keeps_most_recent_assignment() {
+        let (incidents, conflicts) = dedupe(DedupeStrategy::MostRecent, conflicting_incidents());
+        assert_eq!(sys_ids(&incidents), ["1", "4"]);
+        assert_eq!(conflicts.len(), 1);
+    }
+
+    #[test]
+    fn drops_conflicting_titles() {
+        let (incidents, conflicts) = dedupe(DedupeStrategy::Drop, conflicting_incidents());
+        assert_eq!(sys_ids(&incidents), ["4"]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].groups, ["hardware", "network"]);
+    }
+
+    #[test]
+    fn skips_incidents_that_were_already_added() {
+        let normalizer = Normalizer::new(&[]);
+        let redactor = redactor();
+        let mut deduper = IncidentDeduper::new(DedupeStrategy::Last, &normalizer, &redactor);
+        assert!(deduper.add(incident("1", "Printer jammed", "hardware", None)));
+        assert!(!deduper.add(incident("1", "Printer jammed", "network", None)));
+        assert!(deduper.add(incident("2", "VPN down", "network", None)));
+        let (incidents, conflicts) = deduper.deduped(None);
+        assert_eq!(sys_ids(&incidents), ["1", "2"]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn resolves_same_dates_by_position() {
+        let normalizer = Normalizer::new(&[]);
+        let redactor = redactor();
+        let mut deduper = IncidentDeduper::new(DedupeStrategy::MostRecent, &normalizer, &redactor);
+        deduper.add(incident(
+            "1",
+            "Printer jammed",
+            "network",
+            Some("2023-01-02 08:00:00"),
+        ));
+        deduper.add(incident(
+            "2",
+            "Printer jammed",
+            "hardware",
+            Some("2023-01-02 08:00:00"),
+        ));
+        deduper.add(incident(
+            "3",
+            "Printer jammed",
+            "network",
+            Some("2023-01-01 08:00:00"),
+        ));
+        let (incidents, _) = deduper.deduped(None);
+        assert_eq!(sys_ids(&incidents), ["2"]);
+    }
+
+    #[test]
+    fn dedupes_redacted_titles() {
+        let users = [
+            User {
+                name: Some("John Meier".to_string()),
+                user_name: None,
+                email: None,
+            },
+            User {
+                name: Some("Anna Keller".to_string()),
+                user_name: None,
+                email: None,
+            },
+        ];
+        let normalizer = Normalizer::new(&[]);
+        let options = RedactionOptions {
+            detectors: Vec::new(),
+            rules: Vec::new(),
+            file_users: None,
+        };
+        let redactor = Redactor::new(&options, &users).unwrap();
+        let mut deduper = IncidentDeduper::new(DedupeStrategy::Last, &normalizer, &redactor);
+        deduper.add(incident("1", "Call back John Meier", "service_desk", None));
+        deduper.add(incident("2", "Call back Anna Keller", "service_desk", None));
+        deduper.add(incident("3", "Call back Max Muster", "service_desk", None));
+        let (incidents, conflicts) = deduper.deduped(None);
+        let titles: Vec<&str> = incidents
+            .iter()
+            .map(|incident| incident.short_description.as_str())
+            .collect();
+        assert_eq!(titles, ["Call back Anna Keller", "Call back Max Muster"]);
+        assert!(conflicts.is_empty());
     }
-    deduped_incidents
 }
@@ -0,0 +1,495 @@
+/// Loaders for the SNOW (ServiceNow) Table API, to fetch incidents and assignment groups directly from an instance
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+/// Table that contains the incidents
+pub const TABLE_INCIDENT: &str = "incident";
+/// Table that contains the assignment groups
+pub const TABLE_ASSIGNMENT_GROUP: &str = "sys_user_group";
+/// Fields of the incidents that are fetched. The name of the assignment group is used as fallback, if the group
+/// itself can't be fetched. The names of the other referenced records are used in the prompts
+pub const FIELDS_INCIDENT: &str =
+    "sys_id,number,short_description,description,category,subcategory,\
+    cmdb_ci,cmdb_ci.name,business_service,business_service.name,location,location.name,\
+    assignment_group,assignment_group.name,opened_at,sys_created_on";
+/// Fields of the assignment groups that are fetched
+pub const FIELDS_ASSIGNMENT_GROUP: &str = "sys_id,name,sys_created_on";
+
+/// Max time to wait before retrying a request, regardless of what the instance requests
+const MAX_RETRY_DELAY_SECS: u64 = 300;
+
+/// Authentication against the Table API
+pub enum Auth {
+    /// Basic authentication with user and password
+    Basic { user: String, password: String },
+    /// OAuth bearer token, that has been obtained beforehand
+    Token(String),
+    /// OAuth password grant, to obtain a bearer token from the instance
+    OAuth {
+        client_id: String,
+        client_secret: String,
+        user: String,
+        password: String,
+    },
+}
+
+/// Options to access the Table API
+pub struct ApiOptions {
+    /// Base URL of the instance (e.g. `https://example.service-now.com`)
+    pub instance: String,
+    /// Authentication against the instance
+    pub auth: Auth,
+    /// Amount of records per request
+    pub page_size: usize,
+    /// Amount of retries of failed or rate-limited requests
+    pub retries: u32,
+    /// Print additional infos
+    pub verbose: bool,
+}
+
+/// Response of the OAuth token endpoint
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Response of the Table API
+#[derive(Deserialize)]
+struct TableResponse {
+    result: Vec<Value>,
+}
+
+/// Client for the Table API of a single instance
+pub struct ApiClient {
+    /// Options to access the Table API
+    options: ApiOptions,
+    /// Value of the `Authorization` header
+    authorization: String,
+    /// HTTP agent, reusing connections between requests
+    agent: ureq::Agent,
+}
+
+impl ApiClient {
+    /// Creates a client. For OAuth password grants, the bearer token is obtained right away
+    ///
+    /// # Arguments
+    ///
+    /// - `options`: [Options][ApiOptions]
+    ///
+    /// # Bails out when
+    ///
+    /// - the OAuth token can't be obtained
+    pub fn new(options: ApiOptions) -> Result<Self> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(120))
+            .build();
+        let authorization = match &options.auth {
+            Auth::Basic { user, password } => {
+                format!("Basic {}", BASE64.encode(format!("{}:{}", user, password)))
+            }
+            Auth::Token(token) => format!("Bearer {}", token),
+            Auth::OAuth {
+                client_id,
+                client_secret,
+                user,
+                password,
+            } => {
+                let url = format!("{}/oauth_token.do", options.instance.trim_end_matches('/'));
+                let response = agent
+                    .post(&url)
+                    .send_form(&[
+                        ("grant_type", "password"),
+                        ("client_id", client_id),
+                        ("client_secret", client_secret),
+                        ("username", user),
+                        ("password", password),
+                    ])
+                    .with_context(|| format!("Unable to obtain OAuth token from {}", url))?;
+                let token: TokenResponse = serde_json::from_str(
+                    &response
+                        .into_string()
+                        .context("Unable to read OAuth token response")?,
+                )
+                .context("Unable to parse OAuth token response")?;
+                format!("Bearer {}", token.access_token)
+            }
+        };
+        Ok(ApiClient {
+            options,
+            authorization,
+            agent,
+        })
+    }
+
+    /// Fetches all records of a table, page by page.
+    ///
+    /// Pages can contain less records than requested, e.g. because ACLs filter some of them or because the instance
+    /// caps the amount of records per request. Hence, the offset advances by the amount of records that were
+    /// returned and the records are fetched until a page is empty. Records that are returned again (as the offset
+    /// refers to the unfiltered records) are only kept once (by `sys_id`). An empty page only ends the table, if the
+    /// offset has reached the total amount of records (`X-Total-Count`), otherwise the next page is fetched
+    ///
+    /// # Arguments
+    ///
+    /// - `table`: Name of the table
+    /// - `fields`: Comma separated list of the fields to fetch (`sysparm_fields`)
+    /// - `query`: Encoded query to filter the records (`sysparm_query`)
+    ///
+    /// # Returns
+    ///
+    /// The raw records
+    ///
+    /// # Bails out when
+    ///
+    /// - a request fails permanently or after all retries
+    /// - a response can't be parsed
+    pub fn fetch_table(
+        &self,
+        table: &str,
+        fields: &str,
+        query: Option<&str>,
+    ) -> Result<Vec<Value>> {
+        let url = format!(
+            "{}/api/now/table/{}",
+            self.options.instance.trim_end_matches('/'),
+            table
+        );
+        let page_size = self.options.page_size.max(1);
+        // Paging by offset requires a stable order
+        let query_ordered = match query {
+            Some(query) => format!("{}^ORDERBYsys_id", query),
+            None => "ORDERBYsys_id".to_string(),
+        };
+        let mut records: Vec<Value> = Vec::new();
+        let mut sys_ids: HashSet<String> = HashSet::new();
+        let mut offset: usize = 0;
+        loop {
+            let offset_raw = offset.to_string();
+            let limit = page_size.to_string();
+            let params = [
+                ("sysparm_query", query_ordered.as_str()),
+                ("sysparm_fields", fields),
+                ("sysparm_limit", limit.as_str()),
+                ("sysparm_offset", offset_raw.as_str()),
+                ("sysparm_exclude_reference_link", "true"),
+            ];
+            let (page, total) = self.fetch_page(&url, &params)?;
+            if page.is_empty() {
+                match total {
+                    Some(total) if offset < total => {
+                        offset += page_size;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+            offset += page.len();
+            for record in page {
+                let is_new = match record.get("sys_id").and_then(Value::as_str) {
+                    Some(sys_id) => sys_ids.insert(sys_id.to_string()),
+                    None => true,
+                };
+                if is_new {
+                    records.push(record);
+                }
+            }
+            if self.options.verbose {
+                println!("{} records of {} fetched", records.len(), table);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Fetches a single page, retrying failed and rate-limited requests
+    ///
+    /// # Arguments
+    ///
+    /// - `url`: URL of the table
+    /// - `params`: Query parameters
+    ///
+    /// # Returns
+    ///
+    /// Tuple with:
+    ///
+    /// - The raw records of the page
+    /// - The total amount of records (`X-Total-Count`), if the instance provides it
+    ///
+    /// # Bails out when
+    ///
+    /// - the request fails permanently (e.g. because of invalid credentials) or after all retries
+    /// - the response can't be parsed
+    fn fetch_page(
+        &self,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<(Vec<Value>, Option<usize>)> {
+        let mut attempt: u32 = 0;
+        loop {
+            let request = self
+                .agent
+                .get(url)
+                .set("Accept", "application/json")
+                .set("Authorization", &self.authorization)
+                .query_pairs(params.iter().copied());
+            let delay = match request.call() {
+                Ok(response) => {
+                    let total = response
+                        .header("X-Total-Count")
+                        .and_then(|total| total.trim().parse::<usize>().ok());
+                    let body = response
+                        .into_string()
+                        .with_context(|| format!("Unable to read response of {}", url))?;
+                    let page: TableResponse = serde_json::from_str(&body)
+                        .with_context(|| format!("Unable to parse response of {}", url))?;
+                    return Ok((page.result, total));
+                }
+                // Rate limited or temporarily unavailable
+                Err(ureq::Error::Status(status, response)) if status == 429 || status >= 500 => {
+                    let retry_after = response
+                        .header("Retry-After")
+                        .and_then(|retry_after| retry_after.trim().parse::<u64>().ok());
+                    if attempt >= self.options.retries {
+                        bail!("Request to {} failed with status {}", url, status)
+                    }
+                    retry_after.unwrap_or_else(|| backoff(attempt))
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    let body = response.into_string().unwrap_or_default();
+                    bail!("Request to {} failed with status {}: {}", url, status, body)
+                }
+                Err(error @ ureq::Error::Transport(_)) => {
+                    if attempt >= self.options.retries {
+                        return Err(error).with_context(|| format!("Request to {} failed", url));
+                    }
+                    backoff(attempt)
+                }
+            };
+            let delay = delay.min(MAX_RETRY_DELAY_SECS);
+            if self.options.verbose {
+                println!("Request to {} failed, retrying in {}s", url, delay);
+            }
+            thread::sleep(Duration::from_secs(delay));
+            attempt += 1;
+        }
+    }
+}
+
+/// Determines the delay before the next retry, doubling with every attempt
+///
+/// # Arguments
+///
+/// - `attempt`: Number of the failed attempt, starting with `0`
+///
+/// # Returns
+///
+/// The delay in seconds
+fn backoff(attempt: u32) -> u64 {
+    2u64.saturating_pow(attempt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// A canned response of the stub server
+    struct StubResponse {
+        status: &'static str,
+        headers: Vec<(&'static str, String)>,
+        body: String,
+    }
+
+    impl StubResponse {
+        /// A page of records with the given internal IDs
+        fn page(sys_ids: &[&str], total: usize) -> Self {
+            let records: Vec<Value> = sys_ids
+                .iter()
+                .map(|sys_id| json!({ "sys_id": sys_id }))
+                .collect();
+            StubResponse {
+                status: "200 OK",
+                headers: vec![("X-Total-Count", total.to_string())],
+                body: json!({ "result": records }).to_string(),
+            }
+        }
+
+        /// A rate-limited response
+        fn rate_limited(retry_after: u64) -> Self {
+            StubResponse {
+                status: "429 Too Many Requests",
+                headers: vec![("Retry-After", retry_after.to_string())],
+                body: String::new(),
+            }
+        }
+    }
+
+    /// A request, as it has been received by the stub server
+    struct StubRequest {
+        /// Path and query of the request
+        target: String,
+        /// Value of the `Authorization` header
+        authorization: Option<String>,
+    }
+
+    impl StubRequest {
+        /// Gets a query parameter (without decoding it)
+        fn param(&self, name: &str) -> Option<&str> {
+            let (_, query) = self.target.split_once('?')?;
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+        }
+    }
+
+    /// Starts a server, that answers each request with the next canned response and closes the connection
+    ///
+    /// # Returns
+    ///
+    /// Tuple with the base URL of the server and the handle of its thread, which returns the received requests
+    fn start_stub_server(responses: Vec<StubResponse>) -> (String, JoinHandle<Vec<StubRequest>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests: Vec<StubRequest> = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut authorization: Option<String> = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("Authorization") {
+                            authorization = Some(value.trim().to_string());
+                        }
+                    }
+                }
+                requests.push(StubRequest {
+                    target: request_line.split(' ').nth(1).unwrap().to_string(),
+                    authorization,
+                });
+                let mut raw = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in &response.headers {
+                    raw.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                raw.push_str("\r\n");
+                raw.push_str(&response.body);
+                stream.write_all(raw.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn client(instance: String, auth: Auth, page_size: usize) -> ApiClient {
+        ApiClient::new(ApiOptions {
+            instance,
+            auth,
+            page_size,
+            retries: 1,
+            verbose: false,
+        })
+        .unwrap()
+    }
+
+    fn sys_ids(records: &[Value]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record["sys_id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fetches_all_pages() {
+        let (url, server) = start_stub_server(vec![
+            StubResponse::rate_limited(0),
+            StubResponse::page(&["a", "b"], 7),
+            // Short page, e.g. capped by the instance or filtered by ACLs
+            StubResponse::page(&["c"], 7),
+            StubResponse::page(&["c", "d"], 7),
+            // Page that has been filtered by ACLs entirely
+            StubResponse::page(&[], 7),
+            StubResponse::page(&["g"], 7),
+            StubResponse::page(&[], 7),
+        ]);
+        let auth = Auth::Basic {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        };
+        let records = client(url, auth, 2)
+            .fetch_table(TABLE_INCIDENT, "sys_id", Some("active=true"))
+            .unwrap();
+        assert_eq!(sys_ids(&records), ["a", "b", "c", "d", "g"]);
+        let requests = server.join().unwrap();
+        let offsets: Vec<&str> = requests
+            .iter()
+            .map(|request| request.param("sysparm_offset").unwrap())
+            .collect();
+        assert_eq!(offsets, ["0", "0", "2", "3", "5", "7", "8"]);
+        for request in &requests {
+            assert!(request.target.starts_with("/api/now/table/incident?"));
+            assert_eq!(request.param("sysparm_limit"), Some("2"));
+            assert_eq!(
+                request.authorization.as_deref(),
+                Some("Basic YWRtaW46c2VjcmV0")
+            );
+        }
+    }
+
+    #[test]
+    fn stops_at_empty_page_without_total() {
+        let (url, server) = start_stub_server(vec![
+            StubResponse::page(&["a"], 1),
+            StubResponse {
+                status: "200 OK",
+                headers: Vec::new(),
+                body: json!({ "result": [] }).to_string(),
+            },
+        ]);
+        let records = client(url, Auth::Token("abc".to_string()), 100)
+            .fetch_table(TABLE_ASSIGNMENT_GROUP, FIELDS_ASSIGNMENT_GROUP, None)
+            .unwrap();
+        assert_eq!(sys_ids(&records), ["a"]);
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            assert_eq!(request.authorization.as_deref(), Some("Bearer abc"));
+        }
+    }
+
+    #[test]
+    fn fails_after_all_retries() {
+        let (url, server) = start_stub_server(vec![
+            StubResponse::rate_limited(0),
+            StubResponse::rate_limited(0),
+        ]);
+        let result = client(url, Auth::Token("abc".to_string()), 100).fetch_table(
+            TABLE_INCIDENT,
+            "sys_id",
+            None,
+        );
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("failed with status 429"), "{}", error);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,315 @@
+/// Loaders for SNOW (ServiceNow) data exports in CSV format (e.g. list views exported from the UI)
+use crate::servicenow::{AssignmentGroup, Incident, User};
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Fields of an incident that can be read from a CSV export
+pub const INCIDENT_FIELDS: [&str; 12] = [
+    "sys_id",
+    "number",
+    "short_description",
+    "description",
+    "category",
+    "subcategory",
+    "cmdb_ci",
+    "business_service",
+    "location",
+    "assignment_group",
+    "opened_at",
+    "sys_created_on",
+];
+
+/// Fields of an assignment group that can be read from a CSV export
+pub const ASSIGNMENT_GROUP_FIELDS: [&str; 3] = ["sys_id", "name", "sys_created_on"];
+
+/// Fields of a user that can be read from a CSV export
+pub const USER_FIELDS: [&str; 3] = ["name", "user_name", "email"];
+
+/// Scope of the column mappings of the incident export (e.g. `incidents.sys_id=Number`)
+const SCOPE_INCIDENTS: &str = "incidents";
+/// Scope of the column mappings of the assignment group export (e.g. `groups.name=Group`)
+const SCOPE_GROUPS: &str = "groups";
+/// Scope of the column mappings of the user export (e.g. `users.name=Full name`)
+const SCOPE_USERS: &str = "users";
+
+/// Reads a CSV export of SNOW incidents, one row at a time
+///
+/// # Arguments
+///
+/// - `reader`: Reader of the export
+/// - `columns`: Mapping between field name and column name (see [validate_columns]). Fields that are not mapped are
+///   read from the column with the same name as the field
+/// - `on_incident`: Called with each incident, in the order of the export
+///
+/// # Returns
+///
+/// The amount of incidents that have been read
+///
+/// # Bails out when
+///
+/// - the content can't be parsed as CSV
+/// - a required column is missing
+/// - a row can't be deserialized to an `Incident`
+pub fn read_incidents_csv<R: Read>(
+    reader: R,
+    columns: &HashMap<String, String>,
+    on_incident: impl FnMut(Incident),
+) -> Result<usize> {
+    read_csv(
+        reader,
+        columns,
+        SCOPE_INCIDENTS,
+        &INCIDENT_FIELDS,
+        on_incident,
+    )
+}
+
+/// Parses a CSV export of assignment groups
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+/// - `columns`: Mapping between field name and column name (see [validate_columns]). Fields that are not mapped are
+///   read from the column with the same name as the field
+///
+/// # Bails out when
+///
+/// - the content can't be parsed as CSV
+/// - a required column is missing
+/// - a row can't be deserialized to an `AssignmentGroup`
+pub fn parse_assignment_groups_csv(
+    input_raw: &str,
+    columns: &HashMap<String, String>,
+) -> Result<Vec<AssignmentGroup>> {
+    let mut assignment_groups: Vec<AssignmentGroup> = Vec::new();
+    read_csv(
+        input_raw.as_bytes(),
+        columns,
+        SCOPE_GROUPS,
+        &ASSIGNMENT_GROUP_FIELDS,
+        |assignment_group| assignment_groups.push(assignment_group),
+    )?;
+    Ok(assignment_groups)
+}
+
+/// Parses a CSV export of users
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+/// - `columns`: Mapping between field name and column name (see [validate_columns]). Fields that are not mapped are
+///   read from the column with the same name as the field
+///
+/// # Bails out when
+///
+/// - the content can't be parsed as CSV
+/// - a row can't be deserialized to a `User`
+pub fn parse_users_csv(input_raw: &str, columns: &HashMap<String, String>) -> Result<Vec<User>> {
+    let mut users: Vec<User> = Vec::new();
+    read_csv(
+        input_raw.as_bytes(),
+        columns,
+        SCOPE_USERS,
+        &USER_FIELDS,
+        |user| users.push(user),
+    )?;
+    Ok(users)
+}
+
+/// Checks, that only known fields are mapped to columns.
+///
+/// A field can be scoped to a single export, by prefixing it with `incidents.`, `groups.` or `users.` (e.g.
+/// `groups.name=Group`). Unscoped fields apply to all exports, but scoped ones take precedence.
+///
+/// # Arguments
+///
+/// - `columns`: Mapping between field name and column name
+///
+/// # Bails out when
+///
+/// - a field is neither a field of an incident, nor of an assignment group, nor of a user
+/// - a scoped field isn't a field of the export of its scope
+pub fn validate_columns(columns: &HashMap<String, String>) -> Result<()> {
+    let mut known_fields: Vec<&str> = INCIDENT_FIELDS.to_vec();
+    for field in ASSIGNMENT_GROUP_FIELDS.into_iter().chain(USER_FIELDS) {
+        if !known_fields.contains(&field) {
+            known_fields.push(field);
+        }
+    }
+    for field in columns.keys() {
+        let (fields, unscoped_field): (&[&str], &str) = match field.split_once('.') {
+            Some((SCOPE_INCIDENTS, field)) => (&INCIDENT_FIELDS, field),
+            Some((SCOPE_GROUPS, field)) => (&ASSIGNMENT_GROUP_FIELDS, field),
+            Some((SCOPE_USERS, field)) => (&USER_FIELDS, field),
+            Some((scope, _)) => bail!(
+                "Unknown scope '{}' of field '{}'. Known scopes are: {}, {}, {}",
+                scope,
+                field,
+                SCOPE_INCIDENTS,
+                SCOPE_GROUPS,
+                SCOPE_USERS
+            ),
+            None => (&known_fields, field.as_str()),
+        };
+        if !fields.contains(&unscoped_field) {
+            bail!(
+                "Unknown field '{}'. Known fields are: {}",
+                field,
+                fields.join(", ")
+            )
+        }
+    }
+    Ok(())
+}
+
+/// Reads a CSV export with a header row, by deserializing the mapped columns of each row to the given struct
+///
+/// # Arguments
+///
+/// - `reader`: Reader of the export
+/// - `columns`: Mapping between field name and column name
+/// - `scope`: Scope of the export, whose scoped mappings take precedence over unscoped ones
+/// - `fields`: Fields of the struct
+/// - `on_entry`: Called with each deserialized row
+///
+/// # Returns
+///
+/// The amount of rows that have been read
+///
+/// # Bails out when
+///
+/// - the content can't be parsed as CSV
+/// - a row can't be deserialized to the struct (e.g. because of a missing column)
+fn read_csv<T: DeserializeOwned, R: Read>(
+    reader: R,
+    columns: &HashMap<String, String>,
+    scope: &str,
+    fields: &[&str],
+    mut on_entry: impl FnMut(T),
+) -> Result<usize> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .context("Unable to parse CSV header")?
+        .clone();
+    // Index of the column for each field, that is available in the export
+    let mut field_indices: Vec<(&str, usize)> = Vec::new();
+    for field in fields {
+        let column = columns
+            .get(&format!("{}.{}", scope, field))
+            .or_else(|| columns.get(*field))
+            .map(String::as_str)
+            .unwrap_or(field);
+        if let Some(idx) = headers.iter().position(|header| header.trim() == column) {
+            field_indices.push((field, idx));
+        }
+    }
+    let mut amount: usize = 0;
+    for (row, record) in reader.records().enumerate() {
+        let record = record.context("Unable to parse CSV row")?;
+        let mut object = Map::new();
+        for (field, idx) in &field_indices {
+            if let Some(value) = record.get(*idx) {
+                object.insert(field.to_string(), Value::String(value.to_string()));
+            }
+        }
+        // The header is the first line
+        let entry = serde_json::from_value(Value::Object(object))
+            .with_context(|| format!("Unable to parse CSV line {}", row + 2))?;
+        on_entry(entry);
+        amount += 1;
+    }
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    /// Export of incidents, as exported from a list view, with the column labels as header
+    const INCIDENTS_CSV: &str = include_str!("../../tests/fixtures/incidents.csv");
+    /// Export of assignment groups, with column labels that differ from the ones of the incidents
+    const GROUPS_CSV: &str = include_str!("../../tests/fixtures/groups.csv");
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn columns() -> HashMap<String, String> {
+        [
+            ("sys_id", "Number"),
+            ("number", "Number"),
+            ("short_description", "Short description"),
+            ("description", "Description"),
+            ("assignment_group", "Assignment group"),
+            ("opened_at", "Opened"),
+            ("groups.sys_id", "Sys ID"),
+            ("groups.name", "Group"),
+            ("groups.sys_created_on", "Created"),
+        ]
+        .into_iter()
+        .map(|(field, column)| (field.to_string(), column.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn reads_incidents_by_columns() {
+        let mut incidents: Vec<Incident> = Vec::new();
+        let amount = read_incidents_csv(INCIDENTS_CSV.as_bytes(), &columns(), |incident| {
+            incidents.push(incident)
+        })
+        .unwrap();
+        assert_eq!(amount, 2);
+        let incident = &incidents[0];
+        assert_eq!(incident.sys_id.as_deref(), Some("INC0010001"));
+        assert_eq!(incident.number.as_deref(), Some("INC0010001"));
+        assert_eq!(incident.short_description, "Printer jammed, floor 3");
+        assert_eq!(
+            incident.description.as_deref(),
+            Some("Paper is stuck\nin tray 2")
+        );
+        assert_eq!(incident.assignment_group, "hardware");
+        assert_eq!(incident.opened_at, Some(datetime("2023-01-02 08:00:00")));
+        // Unmapped fields are read from the column with the same name
+        assert_eq!(
+            incident.sys_created_on,
+            Some(datetime("2023-01-02 07:59:00"))
+        );
+        let incident = &incidents[1];
+        assert_eq!(incident.description, None);
+        assert_eq!(incident.opened_at, None);
+        assert_eq!(incident.opened(), Some(datetime("2023-01-03 09:00:00")));
+    }
+
+    #[test]
+    fn parses_assignment_groups_by_scoped_columns() {
+        let groups = parse_assignment_groups_csv(GROUPS_CSV, &columns()).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].sys_id, "network");
+        assert_eq!(groups[1].name, "Network");
+        assert_eq!(groups[1].sys_created_on, datetime("2021-06-15 12:30:00"));
+    }
+
+    #[test]
+    fn rejects_missing_columns() {
+        let error =
+            read_incidents_csv(INCIDENTS_CSV.as_bytes(), &HashMap::new(), |_| {}).unwrap_err();
+        assert_eq!(error.to_string(), "Unable to parse CSV line 2");
+        let mut columns = columns();
+        columns.remove("groups.sys_id");
+        assert!(parse_assignment_groups_csv(GROUPS_CSV, &columns).is_err());
+    }
+
+    #[test]
+    fn validates_columns() {
+        assert!(validate_columns(&columns()).is_ok());
+        for field in ["title", "groups.number", "teams.name"] {
+            let columns = HashMap::from([(field.to_string(), "Column".to_string())]);
+            assert!(validate_columns(&columns).is_err());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+/// Loaders for SNOW (ServiceNow) XML unload files (`Export → XML`)
+use crate::servicenow::{AssignmentGroup, Incident, User};
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::io::BufRead;
+
+/// Name of the records within an incident unload file
+const TABLE_INCIDENT: &str = "incident";
+/// Name of the records within an assignment group unload file
+const TABLE_ASSIGNMENT_GROUP: &str = "sys_user_group";
+/// Name of the records within a user unload file
+const TABLE_USER: &str = "sys_user";
+/// Attribute of reference fields, which contains the display value of the referenced record
+const ATTRIBUTE_DISPLAY_VALUE: &str = "display_value";
+
+/// Reads an XML unload file of SNOW incidents, one record at a time
+///
+/// # Arguments
+///
+/// - `reader`: Buffered reader of the unload file
+/// - `on_incident`: Called with each incident, in the order of the unload file
+///
+/// # Returns
+///
+/// The amount of incidents that have been read
+///
+/// # Bails out when
+///
+/// - the content isn't valid XML
+/// - a record can't be deserialized to an `Incident`
+pub fn read_incidents_xml<R: BufRead>(
+    reader: R,
+    on_incident: impl FnMut(Incident),
+) -> Result<usize> {
+    read_unload(reader, TABLE_INCIDENT, on_incident)
+}
+
+/// Parses an XML unload file of assignment groups
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Bails out when
+///
+/// - the content isn't valid XML
+/// - a record can't be deserialized to an `AssignmentGroup`
+pub fn parse_assignment_groups_xml(input_raw: &str) -> Result<Vec<AssignmentGroup>> {
+    let mut assignment_groups: Vec<AssignmentGroup> = Vec::new();
+    read_unload(
+        input_raw.as_bytes(),
+        TABLE_ASSIGNMENT_GROUP,
+        |assignment_group| assignment_groups.push(assignment_group),
+    )?;
+    Ok(assignment_groups)
+}
+
+/// Parses an XML unload file of users
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Bails out when
+///
+/// - the content isn't valid XML
+/// - a record can't be deserialized to a `User`
+pub fn parse_users_xml(input_raw: &str) -> Result<Vec<User>> {
+    let mut users: Vec<User> = Vec::new();
+    read_unload(input_raw.as_bytes(), TABLE_USER, |user| users.push(user))?;
+    Ok(users)
+}
+
+/// Reads the records of a table within an unload file (`<unload><TABLE>...</TABLE></unload>`), by deserializing the
+/// fields of each record to the given struct.
+///
+/// The text of a field is used as its value. For reference fields, this is the internal ID of the referenced record.
+/// Their `display_value` attribute is provided as additional field `FIELD.display_value`.
+///
+/// # Arguments
+///
+/// - `reader`: Buffered reader of the unload file
+/// - `table`: Name of the table, whose records have to be read
+/// - `on_entry`: Called with each deserialized record
+///
+/// # Returns
+///
+/// The amount of records that have been read
+///
+/// # Bails out when
+///
+/// - the content isn't valid XML
+/// - a record can't be deserialized to the struct
+fn read_unload<T: DeserializeOwned, R: BufRead>(
+    reader: R,
+    table: &str,
+    mut on_entry: impl FnMut(T),
+) -> Result<usize> {
+    let mut reader = Reader::from_reader(reader);
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut amount: usize = 0;
+    let mut depth: usize = 0;
+    let mut record: Option<Map<String, Value>> = None;
+    let mut field: Option<String> = None;
+    let mut value = String::new();
+    loop {
+        buffer.clear();
+        let event = reader
+            .read_event_into(&mut buffer)
+            .with_context(|| format!("Unable to parse XML at {}", reader.buffer_position()))?;
+        match event {
+            Event::Start(element) => {
+                depth += 1;
+                let name = element_name(&element);
+                // Depth 1 is the `unload` element, 2 the record and 3 a field of the record
+                if depth == 2 && name == table {
+                    record = Some(Map::new());
+                } else if depth == 3 {
+                    if let Some(record) = record.as_mut() {
+                        insert_display_value(record, &name, &element)?;
+                        field = Some(name);
+                        value.clear();
+                    }
+                }
+            }
+            // Empty fields within a record
+            Event::Empty(element) if depth == 2 => {
+                if let Some(record) = record.as_mut() {
+                    let name = element_name(&element);
+                    insert_display_value(record, &name, &element)?;
+                    record.insert(name, Value::String(String::new()));
+                }
+            }
+            Event::Text(text) if field.is_some() => {
+                value.push_str(&text.unescape().context("Unable to unescape XML text")?);
+            }
+            Event::CData(data) if field.is_some() => {
+                value.push_str(&String::from_utf8_lossy(&data.into_inner()));
+            }
+            Event::End(_) => {
+                if depth == 3 {
+                    if let (Some(record), Some(field)) = (record.as_mut(), field.take()) {
+                        record.insert(field, Value::String(value.trim().to_string()));
+                    }
+                } else if depth == 2 {
+                    if let Some(record) = record.take() {
+                        let entry =
+                            serde_json::from_value(Value::Object(record)).with_context(|| {
+                                format!("Unable to parse <{}> record {}", table, amount + 1)
+                            })?;
+                        on_entry(entry);
+                        amount += 1;
+                    }
+                }
+                depth = depth.saturating_sub(1);
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(amount)
+}
+
+/// Gets the name of an XML element
+///
+/// # Arguments
+///
+/// - `element`: The start of the element
+///
+/// # Returns
+///
+/// The (local) name of the element
+fn element_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).to_string()
+}
+
+/// Adds the `display_value` attribute of a field to the record, as field `FIELD.display_value`
+///
+/// # Arguments
+///
+/// - `record`: The fields of the record
+/// - `field`: Name of the field
+/// - `element`: The start of the field element
+///
+/// # Bails out when
+///
+/// - the attributes of the element can't be parsed
+fn insert_display_value(
+    record: &mut Map<String, Value>,
+    field: &str,
+    element: &BytesStart,
+) -> Result<()> {
+    for attribute in element.attributes() {
+        let attribute = attribute.context("Unable to parse XML attribute")?;
+        if attribute.key.local_name().as_ref() != ATTRIBUTE_DISPLAY_VALUE.as_bytes() {
+            continue;
+        }
+        let display_value = attribute
+            .unescape_value()
+            .context("Unable to unescape XML attribute")?;
+        if !display_value.is_empty() {
+            record.insert(
+                format!("{}.{}", field, ATTRIBUTE_DISPLAY_VALUE),
+                Value::String(display_value.to_string()),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    /// Unload file of incidents, with display values of reference fields and empty fields
+    const INCIDENTS_XML: &str = include_str!("../../tests/fixtures/incidents.xml");
+    /// Unload file of assignment groups
+    const GROUPS_XML: &str = include_str!("../../tests/fixtures/groups.xml");
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn reads_incidents_with_display_values() {
+        let mut incidents: Vec<Incident> = Vec::new();
+        let amount = read_incidents_xml(INCIDENTS_XML.as_bytes(), |incident| {
+            incidents.push(incident)
+        })
+        .unwrap();
+        assert_eq!(amount, 2);
+        let incident = &incidents[0];
+        assert_eq!(
+            incident.sys_id.as_deref(),
+            Some("9d385017c611228701d22104cc95c371")
+        );
+        assert_eq!(incident.short_description, "Printer & scanner jammed");
+        assert_eq!(
+            incident.description.as_deref(),
+            Some("Paper is stuck in <b>tray 2</b>")
+        );
+        assert_eq!(
+            incident.assignment_group,
+            "8a4dde73c6112278017a6a4baf547aa7"
+        );
+        assert_eq!(incident.assignment_group_name.as_deref(), Some("Hardware"));
+        assert_eq!(incident.cmdb_ci.as_deref(), Some("PRN-FLOOR3"));
+        assert_eq!(incident.opened_at, Some(datetime("2023-01-02 08:00:00")));
+        assert_eq!(incident.sys_created_on, None);
+        let incident = &incidents[1];
+        assert_eq!(incident.cmdb_ci, None);
+        assert_eq!(incident.opened_at, None);
+        assert_eq!(incident.opened(), Some(datetime("2023-01-03 09:00:00")));
+    }
+
+    #[test]
+    fn parses_assignment_groups() {
+        let groups = parse_assignment_groups_xml(GROUPS_XML).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].sys_id, "8a4dde73c6112278017a6a4baf547aa7");
+        assert_eq!(groups[0].name, "Hardware");
+        assert_eq!(groups[1].sys_created_on, datetime("2021-06-15 12:30:00"));
+    }
+
+    #[test]
+    fn skips_records_of_other_tables() {
+        assert_eq!(
+            read_incidents_xml(GROUPS_XML.as_bytes(), |_| {}).unwrap(),
+            0
+        );
+        assert!(parse_users_xml(INCIDENTS_XML).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_xml() {
+        assert!(read_incidents_xml("<unload><incident></unload>".as_bytes(), |_| {}).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+/// Loader for template files, which contain the templates of the prompts and completions
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+
+/// Templates of the prompts and completions, as stored in a template file (e.g.
+/// `{"prompt": "{{short_description}}\\n\\n###\\n\\n", "completion": " {{category}}"}`)
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    /// Template of the prompts
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// Template of the completions
+    #[serde(default)]
+    pub completion: Option<String>,
+}
+
+/// Parses a template file
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Bails out when
+///
+/// - the content can't be deserialized to a `TemplateConfig`
+pub fn parse_template_config(input_raw: &str) -> Result<TemplateConfig> {
+    let template_config: TemplateConfig =
+        serde_json::from_str(input_raw).context("Unable to parse file")?;
+    Ok(template_config)
+}
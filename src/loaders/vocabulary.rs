@@ -0,0 +1,79 @@
+/// Loader for BPE vocabulary files in the format of tiktoken (e.g. `cl100k_base.tiktoken`)
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
+
+/// Parses a BPE vocabulary file. Each line contains a base64-encoded token and its rank, separated by a space (e.g.
+/// `SGVsbG8= 9906`)
+///
+/// # Arguments
+///
+/// - `input_raw`: Raw file content
+///
+/// # Returns
+///
+/// The rank of each token. Tokens with a lower rank are merged first
+///
+/// # Bails out when
+///
+/// - a line doesn't consist of token and rank
+/// - a token isn't valid base64 or a rank isn't a number
+/// - the file doesn't contain any tokens
+pub fn parse_vocabulary(input_raw: &str) -> Result<HashMap<Vec<u8>, u32>> {
+    let mut ranks: HashMap<Vec<u8>, u32> = HashMap::new();
+    for (idx, line) in input_raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (token, rank) = match line.split_once(' ') {
+            Some((token, rank)) => (token, rank),
+            None => bail!("Expected TOKEN RANK in line {}, got '{}'", idx + 1, line),
+        };
+        let token = BASE64
+            .decode(token)
+            .with_context(|| format!("Invalid token in line {}", idx + 1))?;
+        let rank: u32 = rank
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid rank in line {}", idx + 1))?;
+        ranks.insert(token, rank);
+    }
+    if ranks.is_empty() {
+        bail!("The vocabulary doesn't contain any tokens")
+    }
+    Ok(ranks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tokens_and_ranks() {
+        let ranks = parse_vocabulary("YWI= 0\n\nYmM= 1\r\nIOO/vw== 2\n").unwrap();
+        assert_eq!(ranks.len(), 3);
+        assert_eq!(ranks[b"ab".as_slice()], 0);
+        assert_eq!(ranks[b"bc".as_slice()], 1);
+        assert_eq!(ranks[&[0x20, 0xe3, 0xbf, 0xbf][..]], 2);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let error = parse_vocabulary("YWI= 0\nYmM=").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected TOKEN RANK in line 2, got 'YmM='"
+        );
+        let error = parse_vocabulary("YWI= 0\nYmM= one").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid rank in line 2");
+        let error = parse_vocabulary("Y!I= 0").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid token in line 1");
+        let error = parse_vocabulary("\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The vocabulary doesn't contain any tokens"
+        );
+    }
+}
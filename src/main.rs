@@ -6,17 +6,191 @@
 //! ```
 //! USAGE:
 //!     snow_report_mapper [OPTIONS] <FILE_INCIDENTS> <FILE_ASSIGNMENT_GROUPS> <FILE_OUTPUT>
+//!     snow_report_mapper <SUBCOMMAND>
 //!
 //! ARGS:
-//!     <FILE_INCIDENTS>            Filepath to the SNOW incidents export
+//!     <FILE_INCIDENTS>            Filepath or glob pattern (e.g. `'incidents_2023_*.json'`) of the
+//!                                 SNOW incidents export(s)
 //!     <FILE_ASSIGNMENT_GROUPS>    Filepath to the SNOW export of the assignment groups
 //!     <FILE_OUTPUT>               Filepath where the mapped training file should be stored to
 //!
 //! OPTIONS:
-//!     -h, --help       Print help information
-//!     -s, --stats      Prints additional statistics
-//!     -v, --verbose    Verbose output
-//!     -V, --version    Print version information
+//!     -c, --column <COLUMNS>
+//!             Maps a field to a column of CSV exports (e.g. `short_description="Short description"`).
+//!             Can be repeated. Fields: sys_id, number, short_description, description, category,
+//!             subcategory, cmdb_ci, business_service, location, assignment_group, opened_at,
+//!             sys_created_on, name, user_name, email. Prefix a field with `incidents.`, `groups.` or
+//!             `users.` to map it for a single export only (e.g. `groups.sys_id="Sys ID"`)
+//!
+//!         --completion-template <COMPLETION_TEMPLATE>
+//!             Template of the completions, e.g. `' {{label}}'`. Same placeholders as for the prompts
+//!
+//!     -d, --dedupe <DEDUPE>
+//!             Strategy to resolve titles that were assigned to different groups [default: last]
+//!             [possible values: last, majority, most-recent, drop]
+//!
+//!         --encoding <ENCODING>
+//!             Encoding of the vocabulary, which determines how texts are split into words [default:
+//!             cl100k] [possible values: cl100k, o200k]
+//!
+//!         --epochs <EPOCHS>
+//!             Amount of epochs the model is trained for, to estimate the costs of the training
+//!             [default: 1]
+//!
+//!     -f, --format <FORMAT>
+//!             Format of the output file [default: json] [possible values: json, jsonl]
+//!
+//!     -h, --help
+//!             Print help information
+//!
+//!     -i, --input-format <INPUT_FORMAT>
+//!             Format of the exports. Detected by the file extension, if not set [possible values:
+//!             json, csv, xml]
+//!
+//!         --incidents <FILE_INCIDENTS>
+//!             Additional SNOW incidents export(s) (filepath or glob pattern), that are merged with the
+//!             first one. Incidents that occur in multiple exports are only kept once (by `sys_id`).
+//!             Can be repeated
+//!
+//!         --input-compression <INPUT_COMPRESSION>
+//!             Compression of the exports. Detected by the file extension (`.gz`, `.zst`), if not set
+//!             [possible values: none, gzip, zstd]
+//!
+//!     -l, --label-map <LABEL_MAP>
+//!             Filepath to the label map, which keeps the category IDs stable between runs (created if
+//!             missing)
+//!
+//!         --label-mode <LABEL_MODE>
+//!             How the assignment groups are labeled in the completions (`{{label}}`). Labels other
+//!             than `id` are kept in the label map, to stay stable between runs [default: id] [possible
+//!             values: id, name, slug, token]
+//!
+//!     -m, --mode <MODE>
+//!             Format of the fine-tuning entries [default: completion] [possible values: completion,
+//!             chat]
+//!
+//!         --max-median-factor <MAX_MEDIAN_FACTOR>
+//!             Randomly undersample assignment groups with more incidents than the given multiple of
+//!             the median amount of incidents per group (uses `--seed`)
+//!
+//!         --max-prompt-tokens <MAX_PROMPT_TOKENS>
+//!             Truncate the prompt fields (`{{prompt}}`) to the given amount of tokens, including the
+//!             rest of the prompt template (requires `--vocab`)
+//!
+//!         --max-samples <MAX_SAMPLES>
+//!             Randomly undersample assignment groups with more incidents than the given amount (uses
+//!             `--seed`)
+//!
+//!         --min-samples <MIN_SAMPLES>
+//!             Drop assignment groups with less incidents than the given amount
+//!
+//!         --normalize <NORMALIZE>
+//!             Normalize the fields of the incidents, before the prompts are created and the titles are
+//!             de-duped. Can be repeated or comma-separated, the steps are always applied in the order
+//!             of the possible values [possible values: html, nfkc, whitespace, punctuation, prefixes,
+//!             lowercase, digits]
+//!
+//!     -o, --order <ORDER>
+//!             Order in which the incidents are processed and written (`shuffle` uses `--seed`)
+//!             [default: number] [possible values: number, opened, shuffle]
+//!
+//!         --oversample <OVERSAMPLE>
+//!             Oversample assignment groups with less training entries to the given amount, by
+//!             duplicating random entries (uses `--seed`)
+//!
+//!     -p, --prompt-field <PROMPT_FIELDS>
+//!             Field of the incidents that is added to the prompts, optionally preceded by a label
+//!             (e.g. `category=Category`). Can be repeated, the fields are added line by line in the
+//!             given order. Only the title is used, if not set. Fields: number, short_description,
+//!             description, category, subcategory, cmdb_ci, business_service, location
+//!
+//!         --price <PRICE>
+//!             Price per 1M training tokens, to estimate the costs of the training (requires `--vocab`)
+//!
+//!         --prompt-template <PROMPT_TEMPLATE>
+//!             Template of the prompts, e.g. `$'{{short_description}}\n\n###\n\n'`. Placeholders:
+//!             `{{prompt}}` (built from the prompt fields), `{{category}}`, `{{label}}`,
+//!             `{{group.name}}`, `{{group.sys_id}}` and the fields of the incidents (e.g.
+//!             `{{description}}` or `{{incident.category}}`)
+//!
+//!         --redact <REDACT>
+//!             Redact personal data from the fields of the incidents, by replacing it with a
+//!             placeholder (e.g. `[EMAIL]`). Can be repeated or comma-separated [possible values:
+//!             email, phone, ipv4, ipv6, mac, url]
+//!
+//!         --redact-rule <REDACT_RULES>
+//!             Custom redaction rule in the format `NAME=REGEX` (e.g. `employee_id=\bE\d{6}\b`), whose
+//!             matches are replaced by `[NAME]`. Can be repeated, the rules are applied before the
+//!             built-in detectors (except for `url` and `email`)
+//!
+//!     -s, --stats
+//!             Prints additional statistics
+//!
+//!         --seed <SEED>
+//!             Seed for all randomized steps, to make the output reproducible [default: 0]
+//!
+//!         --sidecar-format <SIDECAR_FORMAT>
+//!             Format of the sidecar file, that links the category IDs to the assignment groups
+//!             [default: json] [possible values: json, csv]
+//!
+//!         --similarity <SIMILARITY>
+//!             De-dupe near-identical titles as well, whose similarity (`0` to `1`, e.g. `0.8`) reaches
+//!             the given threshold. Titles are compared case-insensitively, with numbers and
+//!             punctuation being ignored
+//!
+//!         --split-date <SPLIT_DATE>
+//!             Cut-off date (`YYYY-MM-DD`) for a chronological split. Incidents opened on or after it
+//!             are written to the test file
+//!
+//!         --stop-sequence <STOP_SEQUENCE>
+//!             Stop sequence, that is appended to the completions in `completion` mode (e.g. `' END'`
+//!             or `$'\n'`)
+//!
+//!         --system-prompt <SYSTEM_PROMPT>
+//!             System prompt for the entries in `chat` mode
+//!
+//!     -t, --trim <TRIM>
+//!             Trim the amount of incidents per assigment group by the given amount
+//!
+//!         --template-file <TEMPLATE_FILE>
+//!             Filepath to a JSON file with the templates (`{"prompt": "...", "completion": "..."}`)
+//!
+//!         --test-ratio <TEST_RATIO>
+//!             Ratio of entries that are split off into a test file (e.g. `0.1`)
+//!
+//!         --users <USERS>
+//!             Filepath to an export of the users (`sys_user` with `name`, `user_name` and `email`),
+//!             whose names, login IDs and email addresses are redacted from the fields of the
+//!             incidents. Same formats as the other exports
+//!
+//!     -v, --verbose
+//!             Verbose output
+//!
+//!     -V, --version
+//!             Print version information
+//!
+//!         --validate
+//!             Validates the entries before writing them (like the `validate` subcommand). Warnings are
+//!             printed, errors abort
+//!
+//!         --validation-ratio <VALIDATION_RATIO>
+//!             Ratio of entries that are split off into a validation file (e.g. `0.1`)
+//!
+//!         --vocab <VOCAB>
+//!             Filepath to a BPE vocabulary in the format of tiktoken (e.g. `cl100k_base.tiktoken`), to
+//!             count the tokens of the entries (listed with `--stats`)
+//!
+//!     -z, --compression <COMPRESSION>
+//!             Compression of the output file(s). Detected by the file extension (`.gz`, `.zst`), if
+//!             not set [possible values: none, gzip, zstd]
+//!
+//! SUBCOMMANDS:
+//!     fetch       Fetches the incidents and assignment groups from the Table API of an instance
+//!                     and stores them as JSON exports
+//!     help        Print this message or the help of the given subcommand(s)
+//!     validate    Validates a fine-tuning file (JSON array or JSONL) for common mistakes, like
+//!                     missing separators, duplicates or classes with too few examples. Exits with an
+//!                     error, if the file can't be used as it is
 //! ```
 //!
 //! To get this help, run:
@@ -27,16 +201,20 @@
 //!
 //! ## Arguments:
 //!
-//! 1. Filepath to the ServiceNOW export
+//! 1. Filepath or glob pattern of the ServiceNOW export(s)
 //! 2. Filepath to the assignment groups export
 //! 3. Filepath to where the mapped report should be written to
 
 use crate::loaders::servicenow;
 use crate::stats::Stats;
 use anyhow::Result;
-use snow_report_mapper::{run, RunOptions};
+use snow_report_mapper::{
+    fetch, run, validate, ApiOptions, BalanceOptions, EntryOptions, FetchOptions, RedactionOptions,
+    RunOptions, TokenOptions,
+};
 
 pub mod cli;
+pub mod compression;
 pub mod loaders;
 pub mod mappers;
 pub mod serializers;
@@ -46,14 +224,81 @@ pub mod writers;
 fn main() -> Result<()> {
     let args: cli::Args = cli::parse();
 
+    if let Some(cli::Command::Fetch(fetch_args)) = args.command {
+        return fetch(
+            &fetch_args.file_incidents,
+            &fetch_args.file_assignment_groups,
+            FetchOptions {
+                api: ApiOptions {
+                    auth: fetch_args.auth()?,
+                    instance: fetch_args.instance,
+                    page_size: fetch_args.page_size,
+                    retries: fetch_args.retries,
+                    verbose: fetch_args.verbose,
+                },
+                incident_query: fetch_args.incident_query,
+                group_query: fetch_args.group_query,
+            },
+        );
+    }
+    if let Some(cli::Command::Validate(validate_args)) = args.command {
+        return validate(&validate_args.file);
+    }
+    // Without subcommand, the files are required
+    let mut files_incidents: Vec<String> = args.file_incidents.into_iter().collect();
+    files_incidents.extend(args.more_incidents);
     run(
-        &args.file_incidents,
-        &args.file_assignment_groups,
-        &args.file_output,
+        &files_incidents,
+        &args.file_assignment_groups.unwrap_or_default(),
+        &args.file_output.unwrap_or_default(),
         RunOptions {
             verbose: args.verbose,
             print_stats: args.stats,
             trim: args.trim,
+            input_format: args.input_format,
+            input_compression: args.input_compression,
+            csv_columns: args.columns.into_iter().collect(),
+            dedupe_strategy: args.dedupe,
+            similarity: args.similarity,
+            order: args.order,
+            balance: BalanceOptions {
+                min_samples: args.min_samples,
+                max_samples: args.max_samples,
+                max_median_factor: args.max_median_factor,
+                oversample: args.oversample,
+            },
+            output_format: args.format,
+            output_compression: args.compression,
+            entry: EntryOptions {
+                format: args.mode,
+                prompt_fields: args.prompt_fields,
+                system_prompt: args.system_prompt,
+                prompt_template: args.prompt_template,
+                completion_template: args.completion_template,
+                label_mode: args.label_mode,
+                stop_sequence: args.stop_sequence,
+                max_prompt_tokens: args.max_prompt_tokens,
+            },
+            file_templates: args.template_file,
+            normalization: args.normalize,
+            redaction: RedactionOptions {
+                detectors: args.redact,
+                rules: args.redact_rules,
+                file_users: args.users,
+            },
+            tokens: TokenOptions {
+                file_vocabulary: args.vocab,
+                encoding: args.encoding,
+                epochs: args.epochs,
+                price: args.price,
+            },
+            validate: args.validate,
+            file_label_map: args.label_map,
+            sidecar_format: args.sidecar_format,
+            validation_ratio: args.validation_ratio,
+            test_ratio: args.test_ratio,
+            split_date: args.split_date,
+            seed: args.seed,
         },
     )
 }
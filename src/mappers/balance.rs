@@ -0,0 +1,260 @@
+/// Balances the amount of samples per assignment group
+use crate::loaders::servicenow::Incident;
+use crate::mappers::output::FineTuningEntry;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{BTreeMap, HashSet};
+
+/// Options to balance the amount of samples per assignment group
+pub struct BalanceOptions {
+    /// Assignment groups with less incidents are dropped entirely
+    pub min_samples: Option<usize>,
+    /// Assignment groups with more incidents are randomly undersampled to this amount
+    pub max_samples: Option<usize>,
+    /// Assignment groups with more incidents than this multiple of the median amount of incidents per group are
+    /// randomly undersampled to it
+    pub max_median_factor: Option<f64>,
+    /// Assignment groups with less training entries are oversampled to this amount, by duplicating random entries
+    pub oversample: Option<usize>,
+}
+
+/// Drops assignment groups with too few incidents and randomly undersamples assignment groups with too many incidents.
+///
+/// The median is determined after dropping the small groups. The incidents keep their order.
+///
+/// # Arguments
+///
+/// - `incidents`: The incidents to balance
+/// - `options`: [Options][BalanceOptions]
+/// - `seed`: Seed for the random undersampling
+///
+/// # Returns
+///
+/// The balanced incidents
+pub fn balance_incidents(
+    incidents: Vec<Incident>,
+    options: &BalanceOptions,
+    seed: u64,
+) -> Vec<Incident> {
+    // Ordered map, to process the assignment groups in a reproducible order
+    let mut groups: BTreeMap<&String, Vec<usize>> = BTreeMap::new();
+    for (idx, incident) in incidents.iter().enumerate() {
+        groups
+            .entry(&incident.assignment_group)
+            .or_default()
+            .push(idx);
+    }
+    if let Some(min_samples) = options.min_samples {
+        groups.retain(|_, indices| indices.len() >= min_samples);
+    }
+    let max_samples = determine_max_samples(&groups, options);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut kept: HashSet<usize> = HashSet::new();
+    for (_, mut indices) in groups {
+        if let Some(max_samples) = max_samples {
+            if indices.len() > max_samples {
+                indices.shuffle(&mut rng);
+                indices.truncate(max_samples);
+            }
+        }
+        kept.extend(indices);
+    }
+    incidents
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| kept.contains(idx))
+        .map(|(_, incident)| incident)
+        .collect()
+}
+
+/// Determines the max amount of incidents per assignment group
+///
+/// # Arguments
+///
+/// - `groups`: Indices of the incidents, per assignment group
+/// - `options`: [Options][BalanceOptions]
+///
+/// # Returns
+///
+/// The lower one of the absolute max amount and the multiple of the median, if any is configured
+fn determine_max_samples(
+    groups: &BTreeMap<&String, Vec<usize>>,
+    options: &BalanceOptions,
+) -> Option<usize> {
+    let max_median = match options.max_median_factor {
+        Some(max_median_factor) if !groups.is_empty() => {
+            let mut sizes: Vec<usize> = groups.values().map(|indices| indices.len()).collect();
+            sizes.sort_unstable();
+            let middle = sizes.len() / 2;
+            let median = match sizes.len() % 2 {
+                0 => (sizes[middle - 1] + sizes[middle]) as f64 / 2.0,
+                _ => sizes[middle] as f64,
+            };
+            // Keep at least one incident per group
+            Some(((median * max_median_factor).floor() as usize).max(1))
+        }
+        _ => None,
+    };
+    match (options.max_samples, max_median) {
+        (Some(max_samples), Some(max_median)) => Some(max_samples.min(max_median)),
+        (max_samples, max_median) => max_samples.or(max_median),
+    }
+}
+
+/// Oversamples the entries of labels with too few entries, by adding randomly picked duplicates. If duplicates have
+/// been added, all entries are shuffled, so that the duplicates are spread across the file instead of trailing it.
+///
+/// This should only be applied to training entries, so that duplicates don't leak into validation or test sets.
+///
+/// # Arguments
+///
+/// - `entries`: The entries to oversample
+/// - `min_samples`: The amount of entries, each label is oversampled to
+/// - `seed`: Seed for picking the duplicates
+pub fn oversample_entries(entries: &mut Vec<FineTuningEntry>, min_samples: usize, seed: u64) {
+    // Ordered map, so that the duplicates are picked in the same order for the same seed
+    let mut labels: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        labels
+            .entry(entry.label().to_string())
+            .or_default()
+            .push(idx);
+    }
+    let amount_originals = entries.len();
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    for (_, indices) in labels {
+        for _ in indices.len()..min_samples {
+            // There is at least one entry per label
+            let idx = *indices.choose(&mut rng).unwrap();
+            entries.push(entries[idx].clone());
+        }
+    }
+    if entries.len() > amount_originals {
+        entries.shuffle(&mut rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::servicenow::parse_incidents;
+    use serde_json::json;
+
+    /// Creates an incident per given assignment group, titled by its position
+    fn incidents(groups: &[&str]) -> Vec<Incident> {
+        let records: Vec<serde_json::Value> = groups
+            .iter()
+            .enumerate()
+            .map(|(idx, group)| json!({"short_description": idx.to_string(), "assignment_group": group}))
+            .collect();
+        parse_incidents(&json!({ "records": records }).to_string()).unwrap()
+    }
+
+    fn titles(incidents: &[Incident]) -> Vec<&str> {
+        incidents
+            .iter()
+            .map(|incident| incident.short_description.as_str())
+            .collect()
+    }
+
+    fn options(max_samples: Option<usize>, max_median_factor: Option<f64>) -> BalanceOptions {
+        BalanceOptions {
+            min_samples: None,
+            max_samples,
+            max_median_factor,
+            oversample: None,
+        }
+    }
+
+    fn max_samples(sizes: &[usize], options: &BalanceOptions) -> Option<usize> {
+        let names: Vec<String> = (0..sizes.len()).map(|idx| idx.to_string()).collect();
+        let groups: BTreeMap<&String, Vec<usize>> = names
+            .iter()
+            .zip(sizes)
+            .map(|(name, size)| (name, (0..*size).collect()))
+            .collect();
+        determine_max_samples(&groups, options)
+    }
+
+    fn completion(prompt: &str, label: &str) -> FineTuningEntry {
+        FineTuningEntry::Completion {
+            prompt: prompt.to_string(),
+            completion: label.to_string(),
+        }
+    }
+
+    fn serialize(entries: &[FineTuningEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn determines_max_samples_by_median() {
+        assert_eq!(max_samples(&[10, 1, 3], &options(None, Some(2.0))), Some(6));
+        assert_eq!(
+            max_samples(&[2, 10, 4, 6], &options(None, Some(1.5))),
+            Some(7)
+        );
+        assert_eq!(
+            max_samples(&[2, 10, 4, 6], &options(Some(6), Some(1.5))),
+            Some(6)
+        );
+        assert_eq!(
+            max_samples(&[2, 10, 4, 6], &options(Some(8), None)),
+            Some(8)
+        );
+        assert_eq!(max_samples(&[2, 10, 4, 6], &options(None, None)), None);
+        assert_eq!(max_samples(&[], &options(None, Some(1.5))), None);
+    }
+
+    #[test]
+    fn keeps_at_least_one_sample_per_group() {
+        assert_eq!(max_samples(&[1, 1, 1], &options(None, Some(0.5))), Some(1));
+    }
+
+    #[test]
+    fn balances_incidents_in_order() {
+        let incidents = incidents(&["a", "b", "a", "c", "a", "b", "a", "a"]);
+        let options = BalanceOptions {
+            min_samples: Some(2),
+            max_samples: Some(3),
+            max_median_factor: None,
+            oversample: None,
+        };
+        let balanced = balance_incidents(incidents, &options, 42);
+        let titles = titles(&balanced);
+        assert_eq!(titles.len(), 5);
+        // The single incident of `c` is dropped, 3 of 5 incidents of `a` are kept
+        assert!(titles.contains(&"1") && titles.contains(&"5") && !titles.contains(&"3"));
+        let mut sorted = titles.clone();
+        sorted.sort_by_key(|title| title.parse::<usize>().unwrap());
+        assert_eq!(titles, sorted);
+    }
+
+    #[test]
+    fn oversamples_small_labels() {
+        let originals = vec![
+            completion("0", " 1"),
+            completion("1", " 2"),
+            completion("2", " 2"),
+            completion("3", " 2"),
+        ];
+        let mut entries = originals.clone();
+        oversample_entries(&mut entries, 3, 42);
+        let labels: Vec<&str> = entries.iter().map(|entry| entry.label()).collect();
+        assert_eq!(labels.iter().filter(|label| **label == " 1").count(), 3);
+        assert_eq!(labels.iter().filter(|label| **label == " 2").count(), 3);
+        // The duplicates are spread across the entries
+        let prompts = serialize(&entries);
+        let original_prompts = serialize(&originals);
+        assert_ne!(prompts[..4], original_prompts[..]);
+        // Nothing is added or shuffled, if all labels have enough entries
+        let mut entries = originals.clone();
+        oversample_entries(&mut entries, 1, 42);
+        let prompts = serialize(&entries);
+        assert_eq!(prompts, original_prompts);
+    }
+}
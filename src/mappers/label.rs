@@ -0,0 +1,393 @@
+/// Maps the assignment groups to the labels, that the fine-tuned model completes
+use crate::loaders::label_map::LabelMap;
+use crate::loaders::servicenow::Incident;
+use crate::servicenow::AssignmentGroup;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+
+/// Labels of the `token` mode, in the order they are assigned. Each of them is a single token (with the leading space
+/// of completions) for the tokenizers of OpenAI
+const TOKEN_LABELS: [&str; 52] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l",
+    "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+];
+
+/// Label that is used for group names, that don't contain any letters or digits
+const FALLBACK_SLUG: &str = "group";
+
+/// How the assignment groups are labeled in the completions
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelMode {
+    /// The numeric category ID
+    Id,
+    /// The name of the assignment group
+    Name,
+    /// The name of the assignment group as lowercase slug (e.g. `service-desk`)
+    Slug,
+    /// A single letter, which is a single token
+    Token,
+}
+
+/// Label of an assignment group
+#[derive(Debug)]
+pub struct GroupLabel {
+    /// Category ID of the assignment group
+    pub category: usize,
+    /// Name of the assignment group
+    pub name: String,
+    /// The label, that is used in the completions
+    pub label: String,
+}
+
+impl LabelMode {
+    /// Gets the key under which the labels of this mode are kept in the label map
+    ///
+    /// # Returns
+    ///
+    /// The key. `None` for the `id` mode, as category IDs are labels on their own
+    fn key(&self) -> Option<&'static str> {
+        match self {
+            LabelMode::Id => None,
+            LabelMode::Name => Some("name"),
+            LabelMode::Slug => Some("slug"),
+            LabelMode::Token => Some("token"),
+        }
+    }
+}
+
+/// Maps the assignment groups, that the incidents have been assigned to, to their labels.
+///
+/// Labels are unique. Except for the `id` mode, labels are kept in the label map, so that they stay the same across
+/// runs (e.g. if an assignment group is renamed). New labels never reuse labels of other groups in the label map, even
+/// if these groups don't exist anymore.
+///
+/// # Arguments
+///
+/// - `incidents`: All incidents
+/// - `assignment_groups_indices`: Mapping between internal SNOW assignment group and category ID
+/// - `assignment_groups`: Parsed assignment groups
+/// - `mode`: How the assignment groups are labeled
+/// - `label_map`: The label map, which will be updated with new labels
+///
+/// # Returns
+///
+/// Map with assignment group ID as key and its label as value. Groups without incidents or category ID are missing
+///
+/// # Bails out when
+///
+/// - more groups are labeled in `token` mode, than there are token labels
+pub fn map_group_labels(
+    incidents: &[Incident],
+    assignment_groups_indices: &HashMap<String, usize>,
+    assignment_groups: &[AssignmentGroup],
+    mode: LabelMode,
+    label_map: &mut LabelMap,
+) -> Result<HashMap<String, GroupLabel>> {
+    let assigned_groups: HashSet<&String> = incidents
+        .iter()
+        .map(|incident| &incident.assignment_group)
+        .collect();
+    // Label the groups in the order of their category IDs, to stay deterministic
+    let mut groups: Vec<(usize, &AssignmentGroup)> = assignment_groups
+        .iter()
+        .filter(|assignment_group| assigned_groups.contains(&assignment_group.sys_id))
+        .filter_map(|assignment_group| {
+            assignment_groups_indices
+                .get(&assignment_group.sys_id)
+                .map(|category| (*category, assignment_group))
+        })
+        .collect();
+    groups.sort_by_key(|(category, _)| *category);
+    let mut used_labels: HashSet<String> = match mode.key() {
+        Some(key) => label_map
+            .labels
+            .iter()
+            .filter_map(|label| label.completion_labels.get(key).cloned())
+            .collect(),
+        None => HashSet::new(),
+    };
+    let mut labels_added = false;
+    let mut result: HashMap<String, GroupLabel> = HashMap::new();
+    for (category, assignment_group) in groups {
+        let stored_label = label_map
+            .labels
+            .iter_mut()
+            .find(|label| label.sys_id == assignment_group.sys_id);
+        let label = match (mode.key(), stored_label) {
+            (None, _) => category.to_string(),
+            (Some(key), Some(stored_label)) if stored_label.completion_labels.contains_key(key) => {
+                stored_label.completion_labels[key].clone()
+            }
+            (Some(key), stored_label) => {
+                let label = create_label(mode, &assignment_group.name, category, &used_labels)?;
+                used_labels.insert(label.clone());
+                if let Some(stored_label) = stored_label {
+                    stored_label
+                        .completion_labels
+                        .insert(key.to_string(), label.clone());
+                    labels_added = true;
+                }
+                label
+            }
+        };
+        result.insert(
+            assignment_group.sys_id.clone(),
+            GroupLabel {
+                category,
+                name: assignment_group.name.clone(),
+                label,
+            },
+        );
+    }
+    if labels_added {
+        label_map.version += 1;
+    }
+    Ok(result)
+}
+
+/// Creates a new label for an assignment group
+///
+/// # Arguments
+///
+/// - `mode`: How the assignment group is labeled
+/// - `name`: Name of the assignment group
+/// - `category`: Category ID of the assignment group, which makes ambiguous names unique
+/// - `used_labels`: Labels that are already in use
+///
+/// # Returns
+///
+/// A label, that isn't in use yet
+///
+/// # Bails out when
+///
+/// - all token labels are in use
+fn create_label(
+    mode: LabelMode,
+    name: &str,
+    category: usize,
+    used_labels: &HashSet<String>,
+) -> Result<String> {
+    let label = match mode {
+        LabelMode::Id => return Ok(category.to_string()),
+        LabelMode::Name => name.trim().to_string(),
+        LabelMode::Slug => slugify(name),
+        LabelMode::Token => {
+            return match TOKEN_LABELS
+                .iter()
+                .find(|label| !used_labels.contains(**label))
+            {
+                Some(label) => Ok(label.to_string()),
+                None => bail!(
+                    "Only {} assignment groups can be labeled with single tokens. Use another label mode",
+                    TOKEN_LABELS.len()
+                ),
+            };
+        }
+    };
+    if !used_labels.contains(&label) {
+        return Ok(label);
+    }
+    // Ambiguous labels are made unique by the category ID. As another group might already be labeled like that (e.g.
+    // a group that is actually named `Network (3)`), a counter is added until the label isn't in use
+    let mut attempt: usize = 1;
+    loop {
+        let suffix = match attempt {
+            1 => category.to_string(),
+            _ => format!("{}-{}", category, attempt),
+        };
+        let label_unique = match mode {
+            LabelMode::Name => format!("{} ({})", label, suffix),
+            _ => format!("{}-{}", label, suffix),
+        };
+        if !used_labels.contains(&label_unique) {
+            return Ok(label_unique);
+        }
+        attempt += 1;
+    }
+}
+
+/// Converts a name into a slug, which only consists of lowercase letters, digits and dashes
+///
+/// # Arguments
+///
+/// - `name`: The name
+///
+/// # Returns
+///
+/// The slug (e.g. `service-desk-2nd-level` for `Service Desk (2nd Level)`)
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for char in name.chars().flat_map(char::to_lowercase) {
+        if char.is_alphanumeric() {
+            slug.push(char);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    match slug.is_empty() {
+        true => FALLBACK_SLUG.to_string(),
+        false => slug.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::servicenow::parse_incidents;
+    use crate::mappers::servicenow::map_assignment_groups;
+    use chrono::NaiveDateTime;
+
+    fn assignment_groups(names: &[&str]) -> Vec<AssignmentGroup> {
+        let created =
+            NaiveDateTime::parse_from_str("2021-01-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| AssignmentGroup {
+                sys_id: format!("g{}", idx),
+                name: name.to_string(),
+                sys_created_on: created,
+            })
+            .collect()
+    }
+
+    /// Creates one incident per assignment group
+    fn incidents(assignment_groups: &[AssignmentGroup]) -> Vec<Incident> {
+        let records: Vec<String> = assignment_groups
+            .iter()
+            .map(|assignment_group| {
+                format!(
+                    r#"{{"short_description": "Incident of {}", "assignment_group": "{}"}}"#,
+                    assignment_group.name, assignment_group.sys_id
+                )
+            })
+            .collect();
+        parse_incidents(&format!(r#"{{"records": [{}]}}"#, records.join(","))).unwrap()
+    }
+
+    fn labels(group_labels: &HashMap<String, GroupLabel>) -> Vec<(&str, &str)> {
+        let mut labels: Vec<(&str, &str)> = group_labels
+            .iter()
+            .map(|(sys_id, group_label)| (sys_id.as_str(), group_label.label.as_str()))
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    fn used(labels: &[&str]) -> HashSet<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn keeps_unused_labels() {
+        let label = create_label(LabelMode::Name, " Network ", 3, &used(&[])).unwrap();
+        assert_eq!(label, "Network");
+    }
+
+    #[test]
+    fn makes_ambiguous_labels_unique() {
+        let used_labels = used(&["Network"]);
+        let label = create_label(LabelMode::Name, "Network", 3, &used_labels).unwrap();
+        assert_eq!(label, "Network (3)");
+        let used_labels = used(&["network"]);
+        let label = create_label(LabelMode::Slug, "Network", 3, &used_labels).unwrap();
+        assert_eq!(label, "network-3");
+    }
+
+    #[test]
+    fn never_reuses_labels() {
+        let used_labels = used(&["Network", "Network (3)", "Network (3-2)"]);
+        let label = create_label(LabelMode::Name, "Network", 3, &used_labels).unwrap();
+        assert_eq!(label, "Network (3-3)");
+        let used_labels = used(&["network", "network-3"]);
+        let label = create_label(LabelMode::Slug, "Network", 3, &used_labels).unwrap();
+        assert_eq!(label, "network-3-2");
+    }
+
+    #[test]
+    fn keeps_labels_across_runs() {
+        let mut label_map = LabelMap::new();
+        let mut groups = assignment_groups(&["Network", "network"]);
+        let indices = map_assignment_groups(&mut groups, &mut label_map);
+        let group_labels = map_group_labels(
+            &incidents(&groups),
+            &indices,
+            &groups,
+            LabelMode::Slug,
+            &mut label_map,
+        )
+        .unwrap();
+        assert_eq!(
+            labels(&group_labels),
+            vec![("g0", "network"), ("g1", "network-1")]
+        );
+        assert_eq!(label_map.version, 2);
+        // Renaming a group keeps its label and the label of a removed group isn't reused
+        let mut groups = assignment_groups(&["Networking", "Network"]);
+        groups.remove(0);
+        let indices = map_assignment_groups(&mut groups, &mut label_map);
+        let group_labels = map_group_labels(
+            &incidents(&groups),
+            &indices,
+            &groups,
+            LabelMode::Slug,
+            &mut label_map,
+        )
+        .unwrap();
+        assert_eq!(labels(&group_labels), vec![("g1", "network-1")]);
+        assert_eq!(label_map.version, 2);
+    }
+
+    #[test]
+    fn labels_with_category_ids() {
+        let mut label_map = LabelMap::new();
+        let mut groups = assignment_groups(&["Network", "Service Desk"]);
+        let indices = map_assignment_groups(&mut groups, &mut label_map);
+        let group_labels = map_group_labels(
+            &incidents(&groups),
+            &indices,
+            &groups,
+            LabelMode::Id,
+            &mut label_map,
+        )
+        .unwrap();
+        assert_eq!(labels(&group_labels), vec![("g0", "0"), ("g1", "1")]);
+        assert!(label_map
+            .labels
+            .iter()
+            .all(|label| label.completion_labels.is_empty()));
+    }
+
+    #[test]
+    fn bails_when_token_labels_run_out() {
+        let names: Vec<String> = (0..=TOKEN_LABELS.len())
+            .map(|idx| format!("Group {}", idx))
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut label_map = LabelMap::new();
+        let mut groups = assignment_groups(&names[..TOKEN_LABELS.len()]);
+        let indices = map_assignment_groups(&mut groups, &mut label_map);
+        let group_labels = map_group_labels(
+            &incidents(&groups),
+            &indices,
+            &groups,
+            LabelMode::Token,
+            &mut label_map,
+        )
+        .unwrap();
+        assert_eq!(group_labels["g0"].label, "A");
+        assert_eq!(group_labels["g51"].label, "z");
+        let mut groups = assignment_groups(&names);
+        let indices = map_assignment_groups(&mut groups, &mut label_map);
+        assert!(map_group_labels(
+            &incidents(&groups),
+            &indices,
+            &groups,
+            LabelMode::Token,
+            &mut label_map
+        )
+        .is_err());
+    }
+}
@@ -1,3 +1,13 @@
 /// Module for data mappers
+pub mod balance;
+pub mod label;
+pub mod normalize;
 pub mod output;
+pub mod redact;
 pub mod servicenow;
+pub mod sidecar;
+pub mod similarity;
+pub mod split;
+pub mod template;
+pub mod tokenizer;
+pub mod validate;
@@ -0,0 +1,253 @@
+/// Normalizes the text fields of the incidents, so that formatting artifacts of the exports don't end up in the
+/// prompts
+use crate::loaders::servicenow::{Incident, TEXT_FIELDS};
+use regex::Regex;
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+/// Steps of the normalization, in the order they are applied
+const STEPS: [Normalization; 7] = [
+    Normalization::Html,
+    Normalization::Nfkc,
+    Normalization::Whitespace,
+    Normalization::Punctuation,
+    Normalization::Prefixes,
+    Normalization::Lowercase,
+    Normalization::Digits,
+];
+
+/// Pattern of HTML tags and comments
+const PATTERN_HTML_TAG: &str = r"(?s)<!--.*?-->|</?[A-Za-z][^<>]*>";
+/// Pattern of reply and forward prefixes (e.g. `RE: FW: `) and ticket-number prefixes (e.g. `INC0012345 - ` or
+/// `[RITM0012345] `) at the start of a text
+const PATTERN_PREFIXES: &str = concat!(
+    r"(?i)^(?:\s*(?:(?:re|aw|fw|fwd|wg|tr|sv|vs|antw)\s*:",
+    r"|\[?(?:inc|ritm|req|chg|prb|sctask|task)\d{4,}\]?(?:\s*[-:|]|\s)))+\s*"
+);
+/// Replacement of masked numbers
+const MASKED_NUMBER: &str = "0";
+
+/// Step of the text normalization
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Strip HTML tags and decode HTML entities (e.g. `&amp;`)
+    Html,
+    /// Unicode NFKC normalization (e.g. full-width characters and ligatures)
+    Nfkc,
+    /// Collapse all whitespace (including line breaks) into single spaces and trim the text
+    Whitespace,
+    /// Collapse repeated punctuation (e.g. `!!!` becomes `!`)
+    Punctuation,
+    /// Strip reply and forward prefixes (e.g. `RE: FW: `) and ticket-number prefixes (e.g. `INC0012345 - `)
+    Prefixes,
+    /// Lower-case the text
+    Lowercase,
+    /// Mask numbers, by replacing every sequence of digits with `0`
+    Digits,
+}
+
+/// Normalizes texts by applying the configured steps
+pub struct Normalizer {
+    /// The configured steps, in the order they are applied
+    steps: Vec<Normalization>,
+    /// HTML tags and comments
+    html_tag: Regex,
+    /// Reply, forward and ticket-number prefixes
+    prefixes: Regex,
+    /// Sequences of digits
+    digits: Regex,
+}
+
+impl Normalizer {
+    /// Creates a normalizer for the configured steps. The steps are always applied in the same order: `html`, `nfkc`,
+    /// `whitespace`, `punctuation`, `prefixes`, `lowercase` and `digits`
+    ///
+    /// # Arguments
+    ///
+    /// - `steps`: The configured steps
+    pub fn new(steps: &[Normalization]) -> Self {
+        Normalizer {
+            steps: STEPS
+                .into_iter()
+                .filter(|step| steps.contains(step))
+                .collect(),
+            // None of the patterns depend on the input, so they always compile
+            html_tag: Regex::new(PATTERN_HTML_TAG).unwrap(),
+            prefixes: Regex::new(PATTERN_PREFIXES).unwrap(),
+            digits: Regex::new(r"\d+").unwrap(),
+        }
+    }
+
+    /// Normalizes all text fields of an incident (see [TEXT_FIELDS])
+    ///
+    /// # Arguments
+    ///
+    /// - `incident`: The incident
+    ///
+    /// # Returns
+    ///
+    /// The normalized incident. The incident itself, if no steps are configured
+    pub fn normalize_incident<'a>(&self, incident: &'a Incident) -> Cow<'a, Incident> {
+        if self.steps.is_empty() {
+            return Cow::Borrowed(incident);
+        }
+        let mut incident = incident.clone();
+        for field in TEXT_FIELDS {
+            if let Some(value) = incident.text_field_mut(field) {
+                *value = self.normalize(value).into_owned();
+            }
+        }
+        Cow::Owned(incident)
+    }
+
+    /// Normalizes a text, by applying all configured steps
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    ///
+    /// # Returns
+    ///
+    /// The normalized text. The text itself, if no steps are configured
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.steps.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        let mut normalized = text.to_string();
+        for step in &self.steps {
+            normalized = match step {
+                Normalization::Html => {
+                    let stripped = self.html_tag.replace_all(&normalized, " ");
+                    html_escape::decode_html_entities(&stripped).into_owned()
+                }
+                Normalization::Nfkc => normalized.nfkc().collect(),
+                Normalization::Whitespace => normalized
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+                Normalization::Punctuation => collapse_punctuation(&normalized),
+                Normalization::Prefixes => {
+                    let stripped = self.prefixes.replace(&normalized, "").into_owned();
+                    // Keep texts that consist of nothing else than prefixes (e.g. `RE:`)
+                    match stripped.trim().is_empty() {
+                        true => normalized,
+                        false => stripped,
+                    }
+                }
+                Normalization::Lowercase => normalized.to_lowercase(),
+                Normalization::Digits => self
+                    .digits
+                    .replace_all(&normalized, MASKED_NUMBER)
+                    .into_owned(),
+            };
+        }
+        Cow::Owned(normalized)
+    }
+}
+
+/// Collapses repeated punctuation characters into a single one (e.g. `Help!!!` becomes `Help!`)
+///
+/// # Arguments
+///
+/// - `text`: The text
+///
+/// # Returns
+///
+/// The text without repeated punctuation
+fn collapse_punctuation(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    for char in text.chars() {
+        if char.is_ascii_punctuation() && previous == Some(char) {
+            continue;
+        }
+        collapsed.push(char);
+        previous = Some(char);
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(steps: &[Normalization], text: &str) -> String {
+        Normalizer::new(steps).normalize(text).into_owned()
+    }
+
+    #[test]
+    fn strips_prefixes() {
+        let steps = [Normalization::Prefixes];
+        assert_eq!(
+            normalize(&steps, "RE: FW: INC0012345 - VPN down"),
+            "VPN down"
+        );
+        assert_eq!(
+            normalize(&steps, "aw:[RITM0012345] Access request"),
+            "Access request"
+        );
+        assert_eq!(
+            normalize(&steps, "INC0012345: RE: Printer jammed"),
+            "Printer jammed"
+        );
+        assert_eq!(
+            normalize(&steps, "Reset password of INC0012345"),
+            "Reset password of INC0012345"
+        );
+        // Texts that consist of nothing else than prefixes are kept
+        assert_eq!(normalize(&steps, "RE: FW:"), "RE: FW:");
+    }
+
+    #[test]
+    fn strips_html() {
+        let steps = [Normalization::Html, Normalization::Whitespace];
+        assert_eq!(
+            normalize(
+                &steps,
+                "VPN &amp; WiFi <b>down</b><!-- <p>generated</p>\n -->!"
+            ),
+            "VPN & WiFi down !"
+        );
+        assert_eq!(
+            normalize(&steps, "Disk usage < 10% > limit"),
+            "Disk usage < 10% > limit"
+        );
+    }
+
+    #[test]
+    fn applies_steps_in_fixed_order() {
+        let text = "RE: FW: INC0012345 - VPN &amp; WiFi <b>down</b>!!! since 08:15";
+        assert_eq!(
+            normalize(
+                &[
+                    Normalization::Digits,
+                    Normalization::Whitespace,
+                    Normalization::Html
+                ],
+                text
+            ),
+            "RE: FW: INC0 - VPN & WiFi down !!! since 0:0"
+        );
+        assert_eq!(
+            normalize(
+                &[
+                    Normalization::Lowercase,
+                    Normalization::Punctuation,
+                    Normalization::Prefixes,
+                    Normalization::Nfkc,
+                    Normalization::Html,
+                ],
+                text
+            ),
+            "vpn & wifi  down ! since 08:15"
+        );
+    }
+
+    #[test]
+    fn keeps_text_without_steps() {
+        assert!(matches!(
+            Normalizer::new(&[]).normalize("RE: VPN down"),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -1,22 +1,128 @@
-use crate::loaders::servicenow::Incident;
-use crate::servicenow::AssignmentGroup;
+use crate::loaders::servicenow::{Incident, TEXT_FIELDS};
+use crate::mappers::label::{GroupLabel, LabelMode};
+use crate::mappers::normalize::Normalizer;
+use crate::mappers::redact::Redactor;
+use crate::mappers::template::{EntryTemplates, TemplateValues};
+use crate::mappers::tokenizer::Tokenizer;
 use crate::Stats;
 use anyhow::bail;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// System prompt that is used for chat entries, if none has been configured
+pub const DEFAULT_SYSTEM_PROMPT: &str =
+    "Categorize the ServiceNow incident into the assignment group it has to be assigned to.";
+
+/// Field that is used for the prompts, if no prompt fields have been configured
+const DEFAULT_PROMPT_FIELD: &str = "short_description";
+
+/// A field of the incidents that is added to the prompts
+#[derive(Clone, Debug)]
+pub struct PromptField {
+    /// Name of the field (see [TEXT_FIELDS])
+    pub name: String,
+    /// Label that precedes the value of the field (e.g. `Category: Network`). The value stands on its own, if not set
+    pub label: Option<String>,
+}
+
+/// Options to create the fine-tuning entries
+pub struct EntryOptions {
+    /// Format of the generated fine-tuning entries
+    pub format: EntryFormat,
+    /// Fields of the incidents that make up the prompts, in this order. Only the title is used, if empty
+    pub prompt_fields: Vec<PromptField>,
+    /// System prompt for chat entries. Falls back to a generic instruction, if not set
+    pub system_prompt: Option<String>,
+    /// Template of the prompts. Takes precedence over the template file
+    pub prompt_template: Option<String>,
+    /// Template of the completions. Takes precedence over the template file
+    pub completion_template: Option<String>,
+    /// How the assignment groups are labeled in the completions
+    pub label_mode: LabelMode,
+    /// Stop sequence, that is appended to the completions (only for completion entries)
+    pub stop_sequence: Option<String>,
+    /// Maximum amount of tokens of the prompts (or the user messages of chat entries). Requires a tokenizer
+    pub max_prompt_tokens: Option<usize>,
+}
+
+/// Processors of the text fields of the incidents, which are applied before the entries are created
+pub struct TextProcessors {
+    /// Normalizes the text fields
+    pub normalizer: Normalizer,
+    /// Redacts personal data from the normalized text fields
+    pub redactor: Redactor,
+    /// Counts the tokens of the entries and truncates the prompts, if a vocabulary has been configured
+    pub tokenizer: Option<Tokenizer>,
+}
+
+/// Format of the generated fine-tuning entries
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryFormat {
+    /// Legacy `prompt`/`completion` pairs
+    Completion,
+    /// Chat-completions `messages` (system, user and assistant message)
+    Chat,
+}
+
 /// Represents an OpenAI model training entry
 ///
 /// # See also
 ///
 /// - <https://beta.openai.com/docs/guides/fine-tuning/data-formatting>
 /// - <https://beta.openai.com/docs/guides/fine-tuning/preparing-your-dataset>
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FineTuningEntry {
-    /// Title of the ServiceNOW incident
-    prompt: String,
-    /// Group to which the incident was assigned to
-    completion: String,
+/// - <https://platform.openai.com/docs/guides/fine-tuning/example-format>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FineTuningEntry {
+    /// Entry for legacy completion models
+    Completion {
+        /// Title (and further fields) of the ServiceNOW incident
+        prompt: String,
+        /// Label of the group to which the incident was assigned to
+        completion: String,
+    },
+    /// Entry for chat models
+    Chat {
+        /// The conversation, consisting of system prompt, incident title and assigned group
+        messages: Vec<ChatMessage>,
+    },
+}
+
+impl FineTuningEntry {
+    /// Gets the label of the entry, which is the completion or the content of the assistant message respectively
+    ///
+    /// # Returns
+    ///
+    /// The label of the entry
+    pub fn label(&self) -> &str {
+        match self {
+            FineTuningEntry::Completion { completion, .. } => completion,
+            FineTuningEntry::Chat { messages } => messages
+                .iter()
+                .rev()
+                .find(|message| message.role == ChatRole::Assistant)
+                .map(|message| message.content.as_str())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A single message of a chat fine-tuning entry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    /// Author of the message
+    pub role: ChatRole,
+    /// Content of the message
+    pub content: String,
+}
+
+/// Author of a chat message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
 }
 
 /// Maps the ServiceNOW report data to OpenAI training data
@@ -24,10 +130,12 @@ pub struct FineTuningEntry {
 /// # Arguments
 ///
 /// - `incidents`: Parsed ServiceNow incidents
-/// - `assignment_groups_indices`: Mapping between internal SNOW assignment group and processable OpenAI ID
-/// - `assignment_groups`: Parsed assignment groups
+/// - `group_labels`: Mapping between internal SNOW assignment group and its category ID and label
 /// - `trim`: The max amount of entries per assignment group, after which additional incidents will be dropped (to
 ///   get a more even distribution
+/// - `options`: [Options][EntryOptions] to create the entries
+/// - `templates`: Templates of the prompts and completions
+/// - `processors`: Normalize the text fields of the incidents and redact personal data from them
 /// - `stats`: Struct to hold mapping statistics
 ///
 /// # Returns
@@ -37,15 +145,16 @@ pub struct FineTuningEntry {
 /// # Bails out when
 ///
 /// - an incident has been assigned to an unknown assignment group
-pub fn map_data<'a, 'b>(
-    incidents: &'a Vec<Incident>,
-    assignment_groups_indices: &'a HashMap<String, usize>,
-    assignment_groups: &'a Vec<AssignmentGroup>,
+pub fn map_data(
+    incidents: &Vec<Incident>,
+    group_labels: &HashMap<String, GroupLabel>,
     trim: &Option<usize>,
-    stats: &'b mut Stats,
+    options: &EntryOptions,
+    templates: &EntryTemplates,
+    processors: &TextProcessors,
+    stats: &mut Stats,
 ) -> anyhow::Result<Vec<FineTuningEntry>> {
     let mut result: Vec<FineTuningEntry> = Vec::new();
-    let lookup = create_assignment_group_lookup(assignment_groups);
     let trim_unwrapped = trim.unwrap_or(0);
     let mut distribution: HashMap<String, usize> = HashMap::new();
 
@@ -59,66 +168,357 @@ pub fn map_data<'a, 'b>(
             distribution.insert(entry.assignment_group.clone(), count);
         }
 
-        let idx_assignment_group = assignment_groups_indices.get(&entry.assignment_group);
-        if idx_assignment_group.is_none() {
-            bail!(format!(
-                "Unknown assignment group {}",
-                &entry.assignment_group
-            ))
+        let group_label = match group_labels.get(&entry.assignment_group) {
+            Some(group_label) => group_label,
+            None => bail!(unknown_assignment_group(entry)),
+        };
+        stats.inc_distribution(&group_label.name, group_label.category);
+        let incident = processors.normalizer.normalize_incident(entry);
+        let incident = processors.redactor.redact_incident(&incident, stats);
+        let prompt = create_prompt(&incident, &options.prompt_fields);
+        let mut values = TemplateValues {
+            prompt: &prompt,
+            category: group_label.category,
+            label: &group_label.label,
+            group_name: &group_label.name,
+            group_sys_id: &entry.assignment_group,
+            incident: &incident,
+        };
+        let tokenizer = match &processors.tokenizer {
+            Some(tokenizer) => tokenizer,
+            None => {
+                result.push(create_entry(&values, options, templates));
+                continue;
+            }
+        };
+        if let Some(max_prompt_tokens) = options.max_prompt_tokens {
+            if truncate_prompt(&mut values, templates, tokenizer, max_prompt_tokens)? {
+                stats.tokens.truncated_prompts += 1;
+            }
         }
-        let category = *idx_assignment_group.unwrap();
-        let group_name = get_group_name(entry, &lookup)?;
-        stats.inc_distribution(group_name, category);
-        result.push(FineTuningEntry {
-            // See: https://beta.openai.com/docs/guides/fine-tuning/data-formatting
-            prompt: format!("{}\n\n###\n\n", &entry.short_description).clone(),
-            // The completion should be a string with a leading space
-            completion: format!(" {}", category).clone(),
-        })
+        let entry = create_entry(&values, options, templates);
+        stats.add_entry_tokens(tokenizer.count_entry(&entry));
+        result.push(entry);
     }
     Ok(result)
 }
 
-/// Creates a mapping table between the assignment group ID and the name, for faster lookup
+/// Checks, that only known fields are used for the prompts
+///
+/// # Arguments
+///
+/// - `prompt_fields`: Fields of the incidents that make up the prompts
+///
+/// # Bails out when
+///
+/// - a field isn't a text field of an incident
+pub fn validate_prompt_fields(prompt_fields: &[PromptField]) -> anyhow::Result<()> {
+    for prompt_field in prompt_fields {
+        if !TEXT_FIELDS.contains(&prompt_field.name.as_str()) {
+            bail!(
+                "Unknown prompt field '{}'. Known fields are: {}",
+                prompt_field.name,
+                TEXT_FIELDS.join(", ")
+            )
+        }
+    }
+    Ok(())
+}
+
+/// Creates the prompt for an incident, by joining the values of the prompt fields line by line. Fields without value
+/// are skipped
 ///
 /// # Arguments
 ///
-/// - `assignment_groups`: List of assignment groups for which the mapping shall be created
+/// - `incident`: The incident
+/// - `prompt_fields`: Fields of the incidents that make up the prompts. Only the title is used, if empty
 ///
 /// # Returns
 ///
-/// A mapping between ID and name
-fn create_assignment_group_lookup(
-    assignment_groups: &Vec<AssignmentGroup>,
-) -> HashMap<&String, &String> {
-    let mut lookup = HashMap::new();
-    for entry in assignment_groups {
-        lookup.insert(&entry.sys_id, &entry.name);
+/// The prompt (without separator)
+fn create_prompt(incident: &Incident, prompt_fields: &[PromptField]) -> String {
+    if prompt_fields.is_empty() {
+        return incident
+            .text_field(DEFAULT_PROMPT_FIELD)
+            .unwrap_or_default()
+            .to_string();
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for prompt_field in prompt_fields {
+        let value = match incident.text_field(&prompt_field.name) {
+            Some(value) if !value.trim().is_empty() => value.trim(),
+            _ => continue,
+        };
+        match &prompt_field.label {
+            Some(label) => lines.push(format!("{}: {}", label, value)),
+            None => lines.push(value.to_string()),
+        }
     }
-    lookup
+    lines.join("\n")
 }
 
-/// Gets the name for the assigned group, of an incident
+/// Truncates the prompt (`{{prompt}}`), so that the rendered prompt template doesn't exceed the token budget. The rest
+/// of the template (e.g. the separator) is kept
 ///
 /// # Arguments
 ///
-/// - `entry`: Single ServiceNow incident
-/// - `lookup`: Lookup map between ID and name
+/// - `values`: Values of the template variables of the incident, whose prompt is truncated
+/// - `templates`: Templates of the prompt and completion
+/// - `tokenizer`: Tokenizer to count the tokens
+/// - `max_tokens`: Maximum amount of tokens of the rendered prompt template
+///
+/// # Returns
+///
+/// `true`, if the prompt has been truncated
 ///
 /// # Bails out when
 ///
-/// - an incident is assigned a group that is not available in the lookup map
-fn get_group_name<'a, 'b>(
-    entry: &'a Incident,
-    lookup: &'b HashMap<&'a String, &'a String>,
-) -> anyhow::Result<&'a String> {
-    let group_name_wrapped = lookup.get(&entry.assignment_group);
-    let group_name = match group_name_wrapped.is_none() {
-        true => bail!(format!(
-            "Unknown assignment group {}",
-            &entry.assignment_group
-        )),
-        false => *group_name_wrapped.unwrap(),
-    };
-    Ok(group_name)
+/// - the rendered prompt template exceeds the token budget even without prompt (e.g. because of long incident fields
+///   that are rendered by the template)
+fn truncate_prompt(
+    values: &mut TemplateValues,
+    templates: &EntryTemplates,
+    tokenizer: &Tokenizer,
+    max_tokens: usize,
+) -> anyhow::Result<bool> {
+    let amount_tokens = tokenizer.count(&templates.prompt.render(values));
+    if amount_tokens <= max_tokens {
+        return Ok(false);
+    }
+    let prompt = values.prompt;
+    let amount_template_tokens = amount_tokens.saturating_sub(tokenizer.count(prompt));
+    let mut amount_prompt_tokens = max_tokens.saturating_sub(amount_template_tokens);
+    // Tokens might be merged differently at the boundaries of the prompt, so it is shortened until it fits
+    loop {
+        values.prompt = tokenizer.truncate(prompt, amount_prompt_tokens).trim_end();
+        let amount_tokens = tokenizer.count(&templates.prompt.render(values));
+        if amount_tokens <= max_tokens {
+            return Ok(true);
+        }
+        if amount_prompt_tokens == 0 {
+            bail!(
+                "The prompt of incident {:?} exceeds the token budget of {} tokens even without the prompt fields \
+                ({} tokens). Raise the budget or shorten the prompt template",
+                values.incident.short_description,
+                max_tokens,
+                amount_tokens
+            )
+        }
+        amount_prompt_tokens -= 1;
+    }
+}
+
+/// Creates a single fine-tuning entry for an incident
+///
+/// # Arguments
+///
+/// - `values`: Values of the template variables of the incident
+/// - `options`: [Options][EntryOptions] to create the entry
+/// - `templates`: Templates of the prompt and completion
+///
+/// # Returns
+///
+/// The fine-tuning entry
+fn create_entry(
+    values: &TemplateValues,
+    options: &EntryOptions,
+    templates: &EntryTemplates,
+) -> FineTuningEntry {
+    let prompt = templates.prompt.render(values);
+    let completion = templates.completion.render(values);
+    match options.format {
+        // See: https://beta.openai.com/docs/guides/fine-tuning/data-formatting
+        EntryFormat::Completion => FineTuningEntry::Completion { prompt, completion },
+        EntryFormat::Chat => FineTuningEntry::Chat {
+            messages: vec![
+                ChatMessage {
+                    role: ChatRole::System,
+                    content: options
+                        .system_prompt
+                        .as_deref()
+                        .unwrap_or(DEFAULT_SYSTEM_PROMPT)
+                        .to_string(),
+                },
+                ChatMessage {
+                    role: ChatRole::User,
+                    content: prompt,
+                },
+                ChatMessage {
+                    role: ChatRole::Assistant,
+                    content: completion,
+                },
+            ],
+        },
+    }
+}
+
+/// Creates the error message for an incident that is assigned to an unknown group
+///
+/// # Arguments
+///
+/// - `entry`: Single ServiceNow incident
+///
+/// # Returns
+///
+/// The error message, containing the name of the group if the export provides it
+fn unknown_assignment_group(entry: &Incident) -> String {
+    match &entry.assignment_group_name {
+        Some(name) => format!(
+            "Unknown assignment group {} ({})",
+            &entry.assignment_group, name
+        ),
+        None => format!("Unknown assignment group {}", &entry.assignment_group),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::servicenow::parse_incidents;
+    use crate::mappers::template::Template;
+    use crate::mappers::tokenizer::Encoding;
+
+    fn incident() -> Incident {
+        parse_incidents(
+            r#"{"records": [{
+                "short_description": "Printer jammed",
+                "assignment_group": "hardware"
+            }]}"#,
+        )
+        .unwrap()
+        .remove(0)
+    }
+
+    fn templates(prompt: &str) -> EntryTemplates {
+        EntryTemplates {
+            prompt: Template::parse(prompt).unwrap(),
+            completion: Template::parse(" {{label}}").unwrap(),
+        }
+    }
+
+    fn values<'a>(prompt: &'a str, incident: &'a Incident) -> TemplateValues<'a> {
+        TemplateValues {
+            prompt,
+            category: 0,
+            label: "1",
+            group_name: "Hardware",
+            group_sys_id: "hardware",
+            incident,
+        }
+    }
+
+    fn entry_options(format: EntryFormat, system_prompt: Option<&str>) -> EntryOptions {
+        EntryOptions {
+            format,
+            prompt_fields: Vec::new(),
+            system_prompt: system_prompt.map(str::to_string),
+            prompt_template: None,
+            completion_template: None,
+            label_mode: LabelMode::Id,
+            stop_sequence: None,
+            max_prompt_tokens: None,
+        }
+    }
+
+    fn prompt_field(name: &str, label: Option<&str>) -> PromptField {
+        PromptField {
+            name: name.to_string(),
+            label: label.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn creates_prompts_from_fields() {
+        let incident = parse_incidents(
+            r#"{"records": [{
+                "short_description": "Printer jammed",
+                "description": "  ",
+                "category": "Hardware",
+                "location": " Berlin ",
+                "assignment_group": "hardware"
+            }]}"#,
+        )
+        .unwrap()
+        .remove(0);
+        assert_eq!(create_prompt(&incident, &[]), "Printer jammed");
+        let prompt_fields = vec![
+            prompt_field("category", Some("Category")),
+            prompt_field("short_description", None),
+            prompt_field("description", Some("Description")),
+            prompt_field("subcategory", Some("Subcategory")),
+            prompt_field("location", Some("Location")),
+        ];
+        assert_eq!(
+            create_prompt(&incident, &prompt_fields),
+            "Category: Hardware\nPrinter jammed\nLocation: Berlin"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_prompt_fields() {
+        assert!(validate_prompt_fields(&[prompt_field("cmdb_ci", None)]).is_ok());
+        assert!(validate_prompt_fields(&[prompt_field("caller_id", None)]).is_err());
+    }
+
+    #[test]
+    fn creates_chat_entries() {
+        let incident = incident();
+        let values = values("Printer jammed", &incident);
+        let templates = EntryTemplates::parse(None, None, None, EntryFormat::Chat).unwrap();
+        let entry = create_entry(&values, &entry_options(EntryFormat::Chat, None), &templates);
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap(),
+            serde_json::json!({"messages": [
+                {"role": "system", "content": DEFAULT_SYSTEM_PROMPT},
+                {"role": "user", "content": "Printer jammed"},
+                {"role": "assistant", "content": "1"}
+            ]})
+        );
+        let options = entry_options(EntryFormat::Chat, Some("Route the incident."));
+        let entry = create_entry(&values, &options, &templates);
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap()["messages"][0]["content"],
+            "Route the incident."
+        );
+    }
+
+    #[test]
+    fn creates_completion_entries() {
+        let incident = incident();
+        let values = values("Printer jammed", &incident);
+        let templates = EntryTemplates::parse(None, None, None, EntryFormat::Completion).unwrap();
+        let options = entry_options(EntryFormat::Completion, Some("Route the incident."));
+        assert_eq!(
+            serde_json::to_value(create_entry(&values, &options, &templates)).unwrap(),
+            serde_json::json!({"prompt": "Printer jammed\n\n###\n\n", "completion": " 1"})
+        );
+    }
+
+    #[test]
+    fn truncates_prompts_to_token_budget() {
+        // Without tokens in the vocabulary, every byte is a token
+        let tokenizer = Tokenizer::new(HashMap::new(), Encoding::Cl100k);
+        let incident = incident();
+        let templates = templates("{{prompt}}\n\n###\n\n");
+        let mut values = values("Printer jammed", &incident);
+        assert!(!truncate_prompt(&mut values, &templates, &tokenizer, 21).unwrap());
+        assert_eq!(values.prompt, "Printer jammed");
+        assert!(truncate_prompt(&mut values, &templates, &tokenizer, 15).unwrap());
+        assert_eq!(values.prompt, "Printer");
+        assert_eq!(templates.prompt.render(&values), "Printer\n\n###\n\n");
+    }
+
+    #[test]
+    fn rejects_templates_that_exceed_token_budget() {
+        let tokenizer = Tokenizer::new(HashMap::new(), Encoding::Cl100k);
+        let incident = incident();
+        let templates = templates("{{short_description}}: {{prompt}}");
+        let mut values = values("Paper is stuck", &incident);
+        let error = truncate_prompt(&mut values, &templates, &tokenizer, 10).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The prompt of incident \"Printer jammed\" exceeds the token budget of 10 tokens even without the prompt \
+             fields (16 tokens). Raise the budget or shorten the prompt template"
+        );
+    }
 }
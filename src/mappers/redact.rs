@@ -0,0 +1,708 @@
+/// Redacts personal data (PII) from the text fields of the incidents, before they are written to the fine-tuning
+/// entries
+use crate::loaders::servicenow::{Incident, User, TEXT_FIELDS};
+use crate::Stats;
+use aho_corasick::AhoCorasick;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+
+/// Built-in detectors, in the order they are applied. URLs and email addresses go first, as they may contain IP
+/// addresses or digits, and phone numbers last, as they are the least specific
+const DETECTORS: [Detector; 6] = [
+    Detector::Url,
+    Detector::Email,
+    Detector::Mac,
+    Detector::Ipv4,
+    Detector::Ipv6,
+    Detector::Phone,
+];
+/// Detectors of values, that enclose other personal data (e.g. a login ID within an email address or a URL). They are
+/// applied before all other rules, as a partial redaction would break up the values, so that they aren't detected
+const ENCLOSING_DETECTORS: [Detector; 2] = [Detector::Url, Detector::Email];
+
+/// Names, login IDs and email addresses of users that are shorter than this, aren't redacted, as they would match
+/// ordinary words
+const MIN_LENGTH_USER_VALUE: usize = 3;
+
+/// Pattern of dates, which look like phone numbers (e.g. `2023-01-15` or `15.01.2023`)
+const PATTERN_DATE: &str = r"^(?:\d{4}[-./]\d{1,2}[-./]\d{1,2}|\d{1,2}[-./]\d{1,2}[-./]\d{2,4})";
+
+/// Built-in detector of personal data
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    /// Email addresses
+    Email,
+    /// Phone numbers with at least 7 digits, like `+41 44 123 45 67` or `(030) 1234567`. Dates are ignored
+    Phone,
+    /// IPv4 addresses
+    Ipv4,
+    /// IPv6 addresses with at least three groups or a digit, like `fe80::1`. Paths like `Face::bad` are ignored
+    Ipv6,
+    /// MAC addresses, like `00:1a:2b:3c:4d:5e` or `001a.2b3c.4d5e`
+    Mac,
+    /// URLs, like `https://intranet.example.com/people/jdoe` or `www.example.com`
+    Url,
+}
+
+/// Options to redact personal data
+pub struct RedactionOptions {
+    /// Built-in detectors that are applied
+    pub detectors: Vec<Detector>,
+    /// Custom rules as tuples of name and regular expression. They are applied before the built-in detectors, except
+    /// for the detectors of URLs and email addresses
+    pub rules: Vec<(String, String)>,
+    /// Filepath to an export of the users (`sys_user`), whose names, login IDs and email addresses are redacted
+    pub file_users: Option<String>,
+}
+
+/// Redacts personal data by replacing all matches of the rules with typed placeholders (e.g. `[EMAIL]`)
+pub struct Redactor {
+    /// The rules, in the order they are applied
+    rules: Vec<RedactionRule>,
+    /// Dates, which aren't redacted as phone numbers
+    dates: Regex,
+}
+
+/// A single rule of the redactor
+struct RedactionRule {
+    /// Name of the rule, under which the redactions are counted
+    name: String,
+    /// The text, that replaces the matches
+    placeholder: String,
+    /// Finds the personal data
+    matcher: Matcher,
+    /// The built-in detector, if it isn't a custom rule. Matches of detectors are verified, before they are replaced
+    detector: Option<Detector>,
+}
+
+/// Finds the personal data of a rule
+enum Matcher {
+    /// Regular expression of a custom rule or a detector
+    Pattern(Regex),
+    /// Values of the users, which are matched as whole words (see [find_values])
+    Values(AhoCorasick),
+}
+
+impl Detector {
+    /// Gets the name of the detector, under which the redactions are counted
+    ///
+    /// # Returns
+    ///
+    /// The name of the detector
+    fn name(&self) -> &'static str {
+        match self {
+            Detector::Email => "email",
+            Detector::Phone => "phone",
+            Detector::Ipv4 => "ipv4",
+            Detector::Ipv6 => "ipv6",
+            Detector::Mac => "mac",
+            Detector::Url => "url",
+        }
+    }
+
+    /// Gets the regular expression of the detector
+    ///
+    /// # Returns
+    ///
+    /// The regular expression. Matches might still have to be verified (see [Redactor::is_valid])
+    fn pattern(&self) -> &'static str {
+        match self {
+            Detector::Email => {
+                r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b"
+            }
+            Detector::Phone => r"(?:\+|\(|\b)\d(?:[ \-./()]{0,2}\d){6,14}\b",
+            Detector::Ipv4 => r"\b(?:\d{1,3}\.){3}\d{1,3}\b",
+            Detector::Ipv6 => concat!(
+                r"\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){7}\b",
+                r"|(?:\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6})?::",
+                r"(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b)?"
+            ),
+            Detector::Mac => concat!(
+                r"\b[0-9A-Fa-f]{2}(?:[:-][0-9A-Fa-f]{2}){5}\b",
+                r"|\b[0-9A-Fa-f]{4}\.[0-9A-Fa-f]{4}\.[0-9A-Fa-f]{4}\b"
+            ),
+            Detector::Url => concat!(
+                r#"\b(?:https?|ftp)://[^\s<>"']*[^\s<>"'.,;:!?)\]]"#,
+                r#"|\bwww\.[^\s<>"']*[^\s<>"'.,;:!?)\]]"#
+            ),
+        }
+    }
+}
+
+impl Redactor {
+    /// Creates a redactor for the configured detectors, rules and users.
+    ///
+    /// The URL and email detectors are applied first, followed by the users, the custom rules and the other built-in
+    /// detectors.
+    ///
+    /// # Arguments
+    ///
+    /// - `options`: [Options][RedactionOptions] of the redaction
+    /// - `users`: Users, whose names, login IDs and email addresses are redacted
+    ///
+    /// # Bails out when
+    ///
+    /// - a rule has no name, or the name is used twice (including the names of the detectors and the user rules)
+    /// - the regular expression of a rule is invalid
+    /// - the values of the users can't be searched (e.g. because there are too many of them)
+    pub fn new(options: &RedactionOptions, users: &[User]) -> Result<Self> {
+        let (enclosing_detectors, detectors): (Vec<Detector>, Vec<Detector>) = DETECTORS
+            .into_iter()
+            .filter(|detector| options.detectors.contains(detector))
+            .partition(|detector| ENCLOSING_DETECTORS.contains(detector));
+        let mut rules: Vec<RedactionRule> = enclosing_detectors
+            .into_iter()
+            .map(create_detector_rule)
+            .collect();
+        rules.extend(create_user_rules(users)?);
+        for (name, pattern) in &options.rules {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("Redaction rule '{}' has no name", pattern)
+            }
+            if rules.iter().any(|rule| rule.name == name)
+                || DETECTORS.iter().any(|detector| detector.name() == name)
+            {
+                bail!("The name of redaction rule '{}' is already in use", name)
+            }
+            rules.push(RedactionRule {
+                name: name.to_string(),
+                placeholder: format!("[{}]", name.to_uppercase()),
+                matcher: Matcher::Pattern(
+                    Regex::new(pattern)
+                        .with_context(|| format!("Invalid pattern of redaction rule '{}'", name))?,
+                ),
+                detector: None,
+            });
+        }
+        rules.extend(detectors.into_iter().map(create_detector_rule));
+        Ok(Redactor {
+            rules,
+            dates: Regex::new(PATTERN_DATE).unwrap(),
+        })
+    }
+
+    /// Redacts all text fields of an incident (see [TEXT_FIELDS])
+    ///
+    /// # Arguments
+    ///
+    /// - `incident`: The incident
+    /// - `stats`: Struct to hold mapping statistics, which counts the redactions per rule
+    ///
+    /// # Returns
+    ///
+    /// The redacted incident. The incident itself, if no rules are configured
+    pub fn redact_incident<'a>(
+        &self,
+        incident: &'a Incident,
+        stats: &mut Stats,
+    ) -> Cow<'a, Incident> {
+        if self.rules.is_empty() {
+            return Cow::Borrowed(incident);
+        }
+        let mut incident = incident.clone();
+        for field in TEXT_FIELDS {
+            if let Some(value) = incident.text_field_mut(field) {
+                *value = self.redact(value, stats);
+            }
+        }
+        Cow::Owned(incident)
+    }
+
+    /// Redacts a text, by applying all rules in their order
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    /// - `stats`: Struct to hold mapping statistics, which counts the redactions per rule
+    ///
+    /// # Returns
+    ///
+    /// The text, with all personal data replaced by placeholders
+    pub fn redact(&self, text: &str, stats: &mut Stats) -> String {
+        self.redact_with(text, |rule, amount| stats.inc_redactions(rule, amount))
+            .into_owned()
+    }
+
+    /// Redacts a text without counting the redactions, e.g. to compare texts
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    ///
+    /// # Returns
+    ///
+    /// The text, with all personal data replaced by placeholders. The text itself, if nothing has been redacted
+    pub fn redact_uncounted<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.redact_with(text, |_, _| {})
+    }
+
+    /// Redacts a text, by applying all rules in their order
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    /// - `on_redactions`: Called with the name of a rule and the amount of values it has redacted
+    ///
+    /// # Returns
+    ///
+    /// The text, with all personal data replaced by placeholders. The text itself, if nothing has been redacted
+    fn redact_with<'a>(
+        &self,
+        text: &'a str,
+        mut on_redactions: impl FnMut(&str, u32),
+    ) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for rule in &self.rules {
+            let matches: Vec<Range<usize>> = match &rule.matcher {
+                Matcher::Pattern(regex) => regex
+                    .find_iter(&redacted)
+                    .filter(|candidate| match rule.detector {
+                        Some(detector) => self.is_valid(detector, candidate.as_str()),
+                        None => true,
+                    })
+                    .map(|candidate| candidate.range())
+                    .collect(),
+                Matcher::Values(values) => find_values(values, &redacted),
+            };
+            if !matches.is_empty() {
+                redacted = Cow::Owned(replace_matches(&redacted, &matches, &rule.placeholder));
+                on_redactions(&rule.name, matches.len() as u32);
+            }
+        }
+        redacted
+    }
+
+    /// Verifies a match of a detector, to sort out false positives
+    ///
+    /// # Arguments
+    ///
+    /// - `detector`: The detector that found the match
+    /// - `candidate`: The matched text
+    ///
+    /// # Returns
+    ///
+    /// `true`, if the match has to be redacted
+    fn is_valid(&self, detector: Detector, candidate: &str) -> bool {
+        match detector {
+            Detector::Ipv4 => candidate.parse::<Ipv4Addr>().is_ok(),
+            // Paths like `Face::bad` or `Cache::add` consist of valid hex groups as well, but rarely have more than two
+            // of them or contain digits
+            Detector::Ipv6 => {
+                let amount_groups = candidate
+                    .split(':')
+                    .filter(|group| !group.is_empty())
+                    .count();
+                let has_digit = candidate.contains(|char: char| char.is_ascii_digit());
+                (amount_groups >= 3 || has_digit) && candidate.parse::<Ipv6Addr>().is_ok()
+            }
+            // Plain numbers (e.g. order numbers) are only redacted, if they look like a phone number
+            Detector::Phone => {
+                let has_prefix = candidate.starts_with(['+', '(', '0']);
+                let has_separator = candidate.contains(|char: char| !char.is_ascii_digit());
+                (has_prefix || has_separator) && !self.dates.is_match(candidate)
+            }
+            Detector::Email | Detector::Mac | Detector::Url => true,
+        }
+    }
+}
+
+/// Creates the rule of a built-in detector
+///
+/// # Arguments
+///
+/// - `detector`: The detector
+///
+/// # Returns
+///
+/// The rule, whose matches are verified by the detector
+fn create_detector_rule(detector: Detector) -> RedactionRule {
+    RedactionRule {
+        name: detector.name().to_string(),
+        placeholder: format!("[{}]", detector.name().to_uppercase()),
+        // Unlike custom rules, the patterns of the detectors are fixed and always compile
+        matcher: Matcher::Pattern(Regex::new(detector.pattern()).unwrap()),
+        detector: Some(detector),
+    }
+}
+
+/// Creates the rules, that redact the names, login IDs and email addresses of users
+///
+/// # Arguments
+///
+/// - `users`: The users
+///
+/// # Returns
+///
+/// The rules for the email addresses (`[EMAIL]`), login IDs (`[USER_NAME]`) and names (`[NAME]`), in this order, as
+/// email addresses often contain the login ID or the name. Rules without values are omitted
+///
+/// # Bails out when
+///
+/// - the values can't be searched
+fn create_user_rules(users: &[User]) -> Result<Vec<RedactionRule>> {
+    let rules = [
+        create_user_rule(
+            "user.email",
+            "[EMAIL]",
+            users.iter().filter_map(|user| user.email.as_ref()),
+        )?,
+        create_user_rule(
+            "user.user_name",
+            "[USER_NAME]",
+            users.iter().filter_map(|user| user.user_name.as_ref()),
+        )?,
+        create_user_rule(
+            "user.name",
+            "[NAME]",
+            users.iter().filter_map(|user| user.name.as_ref()),
+        )?,
+    ];
+    Ok(rules.into_iter().flatten().collect())
+}
+
+/// Creates a rule, that redacts the given values. The values are matched as whole words, regardless of the case of
+/// ASCII letters. Whitespace within values matches any whitespace (e.g. line breaks)
+///
+/// # Arguments
+///
+/// - `name`: Name of the rule
+/// - `placeholder`: The text, that replaces the values
+/// - `values`: The values (e.g. the names of all users)
+///
+/// # Returns
+///
+/// The rule. `None`, if there are no values that are long enough to be redacted
+///
+/// # Bails out when
+///
+/// - the values can't be searched (e.g. because there are too many of them)
+fn create_user_rule<'a>(
+    name: &str,
+    placeholder: &str,
+    values: impl Iterator<Item = &'a String>,
+) -> Result<Option<RedactionRule>> {
+    let mut values: Vec<String> = values
+        .map(|value| value.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|value| value.chars().count() >= MIN_LENGTH_USER_VALUE)
+        .collect();
+    if values.is_empty() {
+        return Ok(None);
+    }
+    values.sort();
+    values.dedup();
+    let matcher = AhoCorasick::builder()
+        .ascii_case_insensitive(true)
+        .build(&values)
+        .with_context(|| format!("Unable to create the redaction rule '{}'", name))?;
+    Ok(Some(RedactionRule {
+        name: name.to_string(),
+        placeholder: placeholder.to_string(),
+        matcher: Matcher::Values(matcher),
+        detector: None,
+    }))
+}
+
+/// Finds the values of a user rule within a text.
+///
+/// Values are only matched as whole words. If values overlap, the leftmost value wins and of the values that start at
+/// the same position the longest one, so that e.g. `John Meier-Huber` isn't redacted as `[NAME]-Huber`
+///
+/// # Arguments
+///
+/// - `values`: Searches the values, with single spaces between their words
+/// - `text`: The text
+///
+/// # Returns
+///
+/// The (byte) ranges of the values within the text, in ascending order and without overlaps
+fn find_values(values: &AhoCorasick, text: &str) -> Vec<Range<usize>> {
+    let (collapsed, offsets) = collapse_whitespace(text);
+    let mut candidates: Vec<Range<usize>> = values
+        .find_overlapping_iter(&collapsed)
+        .map(|candidate| candidate.range())
+        .filter(|candidate| is_whole_word(&collapsed, candidate))
+        .collect();
+    candidates.sort_by_key(|candidate| (candidate.start, Reverse(candidate.end)));
+    let mut matches: Vec<Range<usize>> = Vec::new();
+    let mut end: usize = 0;
+    for candidate in candidates {
+        if candidate.start >= end {
+            end = candidate.end;
+            matches.push(offsets[candidate.start]..offsets[candidate.end]);
+        }
+    }
+    matches
+}
+
+/// Collapses each run of whitespace into a single space, so that values with single spaces match any whitespace
+///
+/// # Arguments
+///
+/// - `text`: The text
+///
+/// # Returns
+///
+/// Tuple with:
+///
+/// - The collapsed text
+/// - The (byte) position within the original text of each byte of the collapsed text, followed by the length of the
+///   original text
+fn collapse_whitespace(text: &str) -> (String, Vec<usize>) {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut offsets: Vec<usize> = Vec::with_capacity(text.len() + 1);
+    let mut is_whitespace_run = false;
+    for (idx, char) in text.char_indices() {
+        if char.is_whitespace() {
+            if !is_whitespace_run {
+                collapsed.push(' ');
+                offsets.push(idx);
+            }
+            is_whitespace_run = true;
+            continue;
+        }
+        is_whitespace_run = false;
+        collapsed.push(char);
+        offsets.extend(idx..idx + char.len_utf8());
+    }
+    offsets.push(text.len());
+    (collapsed, offsets)
+}
+
+/// Checks, that a match isn't part of a longer word. Like word boundaries of regular expressions, this is only checked
+/// at the ends of the match, that are word characters themselves (e.g. not after `Jr.`)
+///
+/// # Arguments
+///
+/// - `text`: The text
+/// - `range`: The (byte) range of the match
+///
+/// # Returns
+///
+/// `true`, if the match is a whole word
+fn is_whole_word(text: &str, range: &Range<usize>) -> bool {
+    let is_word_char =
+        |char: Option<char>| char.is_some_and(|char| char.is_alphanumeric() || char == '_');
+    let candidate = &text[range.clone()];
+    let is_bounded_start = !is_word_char(candidate.chars().next())
+        || !is_word_char(text[..range.start].chars().next_back());
+    let is_bounded_end = !is_word_char(candidate.chars().next_back())
+        || !is_word_char(text[range.end..].chars().next());
+    is_bounded_start && is_bounded_end
+}
+
+/// Replaces the matches of a rule by its placeholder
+///
+/// # Arguments
+///
+/// - `text`: The text
+/// - `matches`: The (byte) ranges of the matches, in ascending order and without overlaps
+/// - `placeholder`: The text, that replaces the matches
+///
+/// # Returns
+///
+/// The text with the replaced matches
+fn replace_matches(text: &str, matches: &[Range<usize>], placeholder: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut start: usize = 0;
+    for range in matches {
+        replaced.push_str(&text[start..range.start]);
+        replaced.push_str(placeholder);
+        start = range.end;
+    }
+    replaced.push_str(&text[start..]);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(detectors: &[Detector]) -> Redactor {
+        let options = RedactionOptions {
+            detectors: detectors.to_vec(),
+            rules: Vec::new(),
+            file_users: None,
+        };
+        Redactor::new(&options, &[]).unwrap()
+    }
+
+    fn redact(detector: Detector, text: &str) -> String {
+        redactor(&[detector]).redact(text, &mut Stats::new())
+    }
+
+    #[test]
+    fn redacts_emails() {
+        assert_eq!(
+            redact(Detector::Email, "Mail from john.doe@example.com bounced"),
+            "Mail from [EMAIL] bounced"
+        );
+        assert_eq!(
+            redact(Detector::Email, "Mail to @support bounced"),
+            "Mail to @support bounced"
+        );
+    }
+
+    #[test]
+    fn redacts_phone_numbers() {
+        assert_eq!(
+            redact(Detector::Phone, "Call +41 44 123 45 67 or (030) 1234567"),
+            "Call [PHONE] or [PHONE]"
+        );
+        assert_eq!(
+            redact(Detector::Phone, "Since 2023-01-15, order 12345678"),
+            "Since 2023-01-15, order 12345678"
+        );
+    }
+
+    #[test]
+    fn redacts_ipv4_addresses() {
+        assert_eq!(
+            redact(Detector::Ipv4, "Host 10.0.12.7 unreachable"),
+            "Host [IPV4] unreachable"
+        );
+        assert_eq!(
+            redact(Detector::Ipv4, "Version 999.1.2.3"),
+            "Version 999.1.2.3"
+        );
+    }
+
+    #[test]
+    fn redacts_ipv6_addresses() {
+        assert_eq!(
+            redact(
+                Detector::Ipv6,
+                "Ping fe80::1 and 2001:db8:85a3:0:0:8a2e:370:7334"
+            ),
+            "Ping [IPV6] and [IPV6]"
+        );
+        assert_eq!(redact(Detector::Ipv6, "Route ::1"), "Route [IPV6]");
+        assert_eq!(
+            redact(Detector::Ipv6, "Gateway dead:beef:cafe::"),
+            "Gateway [IPV6]"
+        );
+    }
+
+    #[test]
+    fn ignores_paths_as_ipv6_addresses() {
+        for text in [
+            "Build of Face::bad failed",
+            "Cache::add",
+            "Error::Timeout",
+            "Call ::new",
+        ] {
+            assert_eq!(redact(Detector::Ipv6, text), text);
+        }
+    }
+
+    #[test]
+    fn redacts_mac_addresses() {
+        assert_eq!(
+            redact(Detector::Mac, "NIC 00:1a:2b:3c:4d:5e or 001a.2b3c.4d5e"),
+            "NIC [MAC] or [MAC]"
+        );
+        assert_eq!(redact(Detector::Mac, "Code 00:1a:2b"), "Code 00:1a:2b");
+    }
+
+    #[test]
+    fn redacts_urls() {
+        assert_eq!(
+            redact(
+                Detector::Url,
+                "See https://intranet.example.com/people/jdoe, or www.example.com."
+            ),
+            "See [URL], or [URL]."
+        );
+    }
+
+    #[test]
+    fn counts_redactions() {
+        let mut stats = Stats::new();
+        let redacted = redactor(&[Detector::Email, Detector::Ipv4])
+            .redact("a@example.com, b@example.com on 10.0.0.1", &mut stats);
+        assert_eq!(redacted, "[EMAIL], [EMAIL] on [IPV4]");
+        assert_eq!(stats.redactions["email"], 2);
+        assert_eq!(stats.redactions["ipv4"], 1);
+    }
+
+    #[test]
+    fn detects_urls_and_emails_first() {
+        let options = RedactionOptions {
+            detectors: vec![Detector::Url, Detector::Email, Detector::Phone],
+            rules: vec![("host".to_string(), r"\bsrv-\d+\b".to_string())],
+            file_users: None,
+        };
+        let redactor = Redactor::new(&options, &[]).unwrap();
+        assert_eq!(
+            redactor.redact_uncounted(
+                "See https://srv-01.example.com/wiki or admin@srv-02.example.com on srv-03"
+            ),
+            "See [URL] or [EMAIL] on [HOST]"
+        );
+    }
+
+    fn user(name: &str, user_name: &str, email: &str) -> User {
+        User {
+            name: Some(name.to_string()),
+            user_name: Some(user_name.to_string()),
+            email: Some(email.to_string()),
+        }
+    }
+
+    fn user_redactor() -> Redactor {
+        let users = [
+            user("John Meier", "jmeier", "john.meier@example.com"),
+            user("John Meier-Huber", "jmeierhu", "jmh@example.com"),
+            user("Al", "al", "al@example.com"),
+            user("Sammy Davis Jr.", "sdavis", "sd@example.com"),
+        ];
+        let options = RedactionOptions {
+            detectors: Vec::new(),
+            rules: Vec::new(),
+            file_users: None,
+        };
+        Redactor::new(&options, &users).unwrap()
+    }
+
+    #[test]
+    fn redacts_users() {
+        let mut stats = Stats::new();
+        let redacted = user_redactor().redact(
+            "JOHN MEIER (jmeier, John.Meier@example.com) called John\n  Meier-Huber",
+            &mut stats,
+        );
+        assert_eq!(redacted, "[NAME] ([USER_NAME], [EMAIL]) called [NAME]");
+        assert_eq!(stats.redactions["user.name"], 2);
+        assert_eq!(stats.redactions["user.user_name"], 1);
+        assert_eq!(stats.redactions["user.email"], 1);
+    }
+
+    #[test]
+    fn redacts_email_addresses_of_users_before_login_ids() {
+        let users = [user("John Doe", "jdoe", "jdoe@corp.com")];
+        let options = RedactionOptions {
+            detectors: vec![Detector::Url],
+            rules: Vec::new(),
+            file_users: None,
+        };
+        let redactor = Redactor::new(&options, &users).unwrap();
+        assert_eq!(
+            redactor.redact_uncounted("Mail jdoe@corp.com, see https://wiki/people/jdoe (jdoe)"),
+            "Mail [EMAIL], see [URL] ([USER_NAME])"
+        );
+    }
+
+    #[test]
+    fn redacts_users_as_whole_words_only() {
+        let text = "Ask sjmeier or jmeier_old, John Meierhans and Al";
+        assert_eq!(user_redactor().redact(text, &mut Stats::new()), text);
+        assert_eq!(
+            user_redactor().redact("Sammy Davis Jr.'s laptop", &mut Stats::new()),
+            "[NAME]'s laptop"
+        );
+    }
+}
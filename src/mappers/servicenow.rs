@@ -1,31 +1,179 @@
 /// Contains mapping functions that are related to SNOW (ServiceNow) data structures
-use crate::servicenow::AssignmentGroup;
-use std::collections::HashMap;
+use crate::loaders::label_map::{Label, LabelMap};
+use crate::servicenow::{AssignmentGroup, Incident, TitleConflict};
+use crate::Stats;
+use chrono::{Local, NaiveDateTime};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Order in which the incidents are processed (and therefore written)
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentOrder {
+    /// Ascending by incident number
+    Number,
+    /// Ascending by the date when the incident was opened
+    Opened,
+    /// Randomly shuffled, based on a seed
+    Shuffle,
+}
 
 /// Creates a map for the internal SNOW assigment group id to an ID that is more suitable for fine tuning the OpenAI model
 /// (see: <https://beta.openai.com/docs/guides/fine-tuning/case-study-categorization-for-email-triage>).
 ///
-/// To make sure that the generated ID does not change for existing assignment groups, when new ones are created, we sort
-/// by creation date of the assignment groups first, before we take the index of the position within the list, as ID.
+/// The IDs are taken from the label map. Assignment groups that are not part of the label map yet, are appended to it.
+/// To keep the order stable within a single run, new assignment groups are sorted by their creation date first. IDs of
+/// groups, that are not part of the export anymore, are never reused.
 ///
 /// # Arguments
 ///
 /// - `assignment_groups`: The raw parsed assignment groups from the SNOW export
+/// - `label_map`: The label map of previous runs (or an empty one), which will be updated with new assignment groups
 ///
 /// # Returns
 ///
 /// Map with assignment group ID as key and mapped ID which can be used for categorization with OpenAI
 pub fn map_assignment_groups(
     assignment_groups: &mut Vec<AssignmentGroup>,
+    label_map: &mut LabelMap,
 ) -> HashMap<String, usize> {
-    let mut result = HashMap::new();
-    for (idx, assignment_group) in order_assignment_groups(assignment_groups)
+    let mut result: HashMap<String, usize> = label_map
+        .labels
+        .iter()
+        .map(|label| (label.sys_id.clone(), label.category))
+        .collect();
+    let first_seen: NaiveDateTime = Local::now().naive_local();
+    let mut labels_added = false;
+    for assignment_group in order_assignment_groups(assignment_groups).iter() {
+        if result.contains_key(&assignment_group.sys_id) {
+            update_label_name(label_map, assignment_group);
+            continue;
+        }
+        let category = label_map.next_category;
+        label_map.labels.push(Label {
+            category,
+            sys_id: assignment_group.sys_id.clone(),
+            name: assignment_group.name.clone(),
+            first_seen,
+            completion_labels: BTreeMap::new(),
+        });
+        label_map.next_category += 1;
+        labels_added = true;
+        result.insert(assignment_group.sys_id.clone(), category);
+    }
+    if labels_added {
+        label_map.version += 1;
+    }
+    result
+}
+
+/// Resolves references to assignment groups by name. Exports of UI list views contain the name of the assignment
+/// group instead of its internal ID. References that don't match the internal ID of any assignment group, but the name
+/// of exactly one, are replaced by the internal ID of that group.
+///
+/// # Arguments
+///
+/// - `incidents`: Incidents, possibly referencing assignment groups by name
+/// - `assignment_groups`: Parsed assignment groups
+///
+/// # Returns
+///
+/// Amount of incidents whose reference has been resolved by name
+pub fn resolve_group_names(
+    incidents: &mut [Incident],
+    assignment_groups: &[AssignmentGroup],
+) -> usize {
+    let known_ids: HashSet<&str> = assignment_groups
+        .iter()
+        .map(|assignment_group| assignment_group.sys_id.as_str())
+        .collect();
+    // Internal ID of each name. `None`, if multiple groups share the name
+    let mut ids_by_name: HashMap<&str, Option<&str>> = HashMap::new();
+    for assignment_group in assignment_groups {
+        ids_by_name
+            .entry(assignment_group.name.as_str())
+            .and_modify(|sys_id| *sys_id = None)
+            .or_insert(Some(assignment_group.sys_id.as_str()));
+    }
+    let mut amount_resolved: usize = 0;
+    for incident in incidents {
+        if known_ids.contains(incident.assignment_group.as_str()) {
+            continue;
+        }
+        if let Some(Some(sys_id)) = ids_by_name.get(incident.assignment_group.trim()) {
+            incident.assignment_group_name = Some(incident.assignment_group.trim().to_string());
+            incident.assignment_group = sys_id.to_string();
+            amount_resolved += 1;
+        }
+    }
+    amount_resolved
+}
+
+/// Adds assignment groups that are referenced by incidents, but missing in the assignment group export. Their names
+/// are taken from the display values of the references, their creation date from the earliest incident.
+///
+/// # Arguments
+///
+/// - `incidents`: Incidents, possibly referencing unknown assignment groups
+/// - `assignment_groups`: Parsed assignment groups, that are extended by the missing ones
+///
+/// # Returns
+///
+/// Amount of added assignment groups
+pub fn add_referenced_groups(
+    incidents: &[Incident],
+    assignment_groups: &mut Vec<AssignmentGroup>,
+) -> usize {
+    let mut known_ids: HashMap<String, usize> = assignment_groups
         .iter()
         .enumerate()
+        .map(|(idx, assignment_group)| (assignment_group.sys_id.clone(), idx))
+        .collect();
+    let amount_known = assignment_groups.len();
+    for incident in incidents {
+        let name = match &incident.assignment_group_name {
+            Some(name) => name,
+            None => continue,
+        };
+        match known_ids.get(&incident.assignment_group) {
+            // Groups of the export are never changed
+            Some(idx) if *idx < amount_known => (),
+            Some(idx) => {
+                let assignment_group = &mut assignment_groups[*idx];
+                if let Some(opened) = incident.opened() {
+                    assignment_group.sys_created_on = assignment_group.sys_created_on.min(opened);
+                }
+            }
+            None => {
+                known_ids.insert(incident.assignment_group.clone(), assignment_groups.len());
+                assignment_groups.push(AssignmentGroup {
+                    sys_id: incident.assignment_group.clone(),
+                    name: name.clone(),
+                    sys_created_on: incident
+                        .opened()
+                        .unwrap_or_else(|| Local::now().naive_local()),
+                });
+            }
+        }
+    }
+    assignment_groups.len() - amount_known
+}
+
+/// Updates the name of an existing label, in case the assignment group has been renamed
+///
+/// # Arguments
+///
+/// - `label_map`: The label map
+/// - `assignment_group`: Assignment group which is already part of the label map
+fn update_label_name(label_map: &mut LabelMap, assignment_group: &AssignmentGroup) {
+    if let Some(label) = label_map
+        .labels
+        .iter_mut()
+        .find(|label| label.sys_id == assignment_group.sys_id)
     {
-        result.insert(assignment_group.sys_id.clone(), idx);
+        label.name = assignment_group.name.clone();
     }
-    result
 }
 
 /// Sorts the assignment groups ascending by the creation date of the assignment group
@@ -41,3 +189,211 @@ fn order_assignment_groups(assignment_groups: &mut Vec<AssignmentGroup>) -> &Vec
     assignment_groups.sort_by(|a, b| a.sys_created_on.partial_cmp(&b.sys_created_on).unwrap());
     assignment_groups
 }
+
+/// Orders the incidents deterministically, so that identical inputs always produce identical outputs.
+///
+/// Incidents are ordered by the given key first and by title second, which is unique after de-duping. Shuffling starts
+/// from the order by number, so that the result only depends on the seed.
+///
+/// # Arguments
+///
+/// - `incidents`: The incidents to order
+/// - `order`: Order of the incidents
+/// - `seed`: Seed for shuffling the incidents
+pub fn order_incidents(incidents: &mut [Incident], order: IncidentOrder, seed: u64) {
+    match order {
+        IncidentOrder::Opened => incidents.sort_by(|a, b| {
+            a.opened()
+                .cmp(&b.opened())
+                .then_with(|| a.short_description.cmp(&b.short_description))
+        }),
+        IncidentOrder::Number | IncidentOrder::Shuffle => incidents.sort_by(|a, b| {
+            a.number
+                .cmp(&b.number)
+                .then_with(|| a.short_description.cmp(&b.short_description))
+        }),
+    }
+    if order == IncidentOrder::Shuffle {
+        incidents.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+    }
+}
+
+/// Collects the groups of conflicting titles in the statistics, by their names
+///
+/// # Arguments
+///
+/// - `title_conflicts`: Titles that were assigned to different groups
+/// - `assignment_groups`: Parsed assignment groups, to look up the names of the groups
+/// - `stats`: Struct to hold mapping statistics
+pub fn collect_title_conflicts(
+    title_conflicts: &[TitleConflict],
+    assignment_groups: &[AssignmentGroup],
+    stats: &mut Stats,
+) {
+    let lookup: HashMap<&String, &String> = assignment_groups
+        .iter()
+        .map(|assignment_group| (&assignment_group.sys_id, &assignment_group.name))
+        .collect();
+    for title_conflict in title_conflicts {
+        // Unknown groups are shown by their internal ID
+        let group_names: Vec<String> = title_conflict
+            .groups
+            .iter()
+            .map(|group| (*lookup.get(group).unwrap_or(&group)).clone())
+            .collect();
+        stats.inc_title_conflicts(&group_names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment_group(sys_id: &str, name: &str, sys_created_on: &str) -> AssignmentGroup {
+        AssignmentGroup {
+            sys_id: sys_id.to_string(),
+            name: name.to_string(),
+            sys_created_on: NaiveDateTime::parse_from_str(sys_created_on, "%Y-%m-%d %H:%M:%S")
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn appends_new_groups_by_creation_date() {
+        let mut label_map = LabelMap::new();
+        let mut assignment_groups = vec![
+            assignment_group("g2", "Network", "2021-02-01 00:00:00"),
+            assignment_group("g1", "Service Desk", "2021-01-01 00:00:00"),
+        ];
+        let mapping = map_assignment_groups(&mut assignment_groups, &mut label_map);
+        assert_eq!(mapping["g1"], 0);
+        assert_eq!(mapping["g2"], 1);
+        assert_eq!(label_map.next_category, 2);
+        assert_eq!(label_map.version, 1);
+    }
+
+    #[test]
+    fn keeps_categories_of_previous_runs() {
+        let mut label_map = LabelMap::new();
+        let mut assignment_groups = vec![
+            assignment_group("g1", "Service Desk", "2021-01-01 00:00:00"),
+            assignment_group("g2", "Network", "2021-02-01 00:00:00"),
+        ];
+        map_assignment_groups(&mut assignment_groups, &mut label_map);
+        // `g1` has been removed, `g2` renamed and `g3` added, although it was created before all other groups
+        let mut assignment_groups = vec![
+            assignment_group("g2", "Networking", "2021-02-01 00:00:00"),
+            assignment_group("g3", "Database", "2020-01-01 00:00:00"),
+        ];
+        let mapping = map_assignment_groups(&mut assignment_groups, &mut label_map);
+        assert_eq!(mapping["g1"], 0);
+        assert_eq!(mapping["g2"], 1);
+        assert_eq!(mapping["g3"], 2);
+        assert_eq!(label_map.next_category, 3);
+        assert_eq!(label_map.version, 2);
+        let names: Vec<&str> = label_map
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Service Desk", "Networking", "Database"]);
+    }
+
+    #[test]
+    fn keeps_version_without_new_groups() {
+        let mut label_map = LabelMap::new();
+        let mut assignment_groups = vec![assignment_group(
+            "g1",
+            "Service Desk",
+            "2021-01-01 00:00:00",
+        )];
+        map_assignment_groups(&mut assignment_groups, &mut label_map);
+        map_assignment_groups(&mut assignment_groups, &mut label_map);
+        assert_eq!(label_map.version, 1);
+        assert_eq!(label_map.labels.len(), 1);
+    }
+
+    fn incident(number: Option<&str>, title: &str, opened_at: Option<&str>) -> Incident {
+        Incident {
+            sys_id: None,
+            number: number.map(str::to_string),
+            short_description: title.to_string(),
+            description: None,
+            category: None,
+            subcategory: None,
+            cmdb_ci: None,
+            business_service: None,
+            location: None,
+            assignment_group: "g1".to_string(),
+            assignment_group_name: None,
+            opened_at: opened_at.map(|opened_at| {
+                NaiveDateTime::parse_from_str(opened_at, "%Y-%m-%d %H:%M:%S").unwrap()
+            }),
+            sys_created_on: None,
+        }
+    }
+
+    fn titles(incidents: &[Incident]) -> Vec<&str> {
+        incidents
+            .iter()
+            .map(|incident| incident.short_description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn orders_by_number_and_title() {
+        let mut incidents = vec![
+            incident(Some("INC0002"), "printer", None),
+            incident(None, "vpn", None),
+            incident(Some("INC0001"), "wifi", None),
+            incident(None, "mail", None),
+            incident(Some("INC0001"), "login", None),
+        ];
+        order_incidents(&mut incidents, IncidentOrder::Number, 42);
+        assert_eq!(
+            titles(&incidents),
+            vec!["mail", "vpn", "login", "wifi", "printer"]
+        );
+    }
+
+    #[test]
+    fn orders_by_open_date_and_title() {
+        let mut incidents = vec![
+            incident(None, "printer", Some("2021-02-01 00:00:00")),
+            incident(None, "wifi", Some("2021-01-01 00:00:00")),
+            incident(None, "vpn", None),
+            incident(None, "login", Some("2021-01-01 00:00:00")),
+        ];
+        order_incidents(&mut incidents, IncidentOrder::Opened, 42);
+        assert_eq!(titles(&incidents), vec!["vpn", "login", "wifi", "printer"]);
+    }
+
+    #[test]
+    fn shuffles_independently_of_input_order() {
+        let mut incidents: Vec<Incident> = (0..20)
+            .map(|idx| {
+                incident(
+                    Some(&format!("INC{:04}", idx)),
+                    &format!("title {}", idx),
+                    None,
+                )
+            })
+            .collect();
+        let mut incidents_reversed: Vec<Incident> = (0..20)
+            .rev()
+            .map(|idx| {
+                incident(
+                    Some(&format!("INC{:04}", idx)),
+                    &format!("title {}", idx),
+                    None,
+                )
+            })
+            .collect();
+        order_incidents(&mut incidents, IncidentOrder::Shuffle, 42);
+        order_incidents(&mut incidents_reversed, IncidentOrder::Shuffle, 42);
+        assert_eq!(titles(&incidents), titles(&incidents_reversed));
+        let mut incidents_other_seed = incidents_reversed.clone();
+        order_incidents(&mut incidents_other_seed, IncidentOrder::Shuffle, 7);
+        assert_ne!(titles(&incidents), titles(&incidents_other_seed));
+    }
+}
@@ -0,0 +1,118 @@
+/// Maps the assignment groups to the labels of the sidecar file, which allows to translate completions back into SNOW
+/// assignment groups
+use crate::mappers::label::GroupLabel;
+use crate::servicenow::AssignmentGroup;
+use crate::Stats;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+
+/// A single label of the sidecar file
+#[derive(Serialize, Debug)]
+pub struct SidecarLabel {
+    /// Category ID of the assignment group
+    pub category: usize,
+    /// Label that is used in the completions. Missing for assignment groups without fine-tuning entries
+    pub label: Option<String>,
+    /// The internal ID of the assignment group within SNOW
+    pub sys_id: String,
+    /// The name of the assignment group
+    pub name: String,
+    /// Amount of fine-tuning entries with this category
+    pub samples: usize,
+}
+
+/// Maps the assignment groups to sidecar labels
+///
+/// # Arguments
+///
+/// - `assignment_groups_indices`: Mapping between internal SNOW assignment group and processable OpenAI ID
+/// - `assignment_groups`: Parsed assignment groups
+/// - `group_labels`: Labels of the assignment groups
+/// - `stats`: Statistics of the mapping, containing the amount of samples per category
+///
+/// # Returns
+///
+/// The labels of all assignment groups, ordered by category ID
+pub fn map_sidecar_labels(
+    assignment_groups_indices: &HashMap<String, usize>,
+    assignment_groups: &Vec<AssignmentGroup>,
+    group_labels: &HashMap<String, GroupLabel>,
+    stats: &Stats,
+) -> Vec<SidecarLabel> {
+    let mut labels: Vec<SidecarLabel> = Vec::new();
+    for assignment_group in assignment_groups {
+        let category = match assignment_groups_indices.get(&assignment_group.sys_id) {
+            Some(category) => *category,
+            None => continue,
+        };
+        labels.push(SidecarLabel {
+            category,
+            label: group_labels
+                .get(&assignment_group.sys_id)
+                .map(|group_label| group_label.label.clone()),
+            sys_id: assignment_group.sys_id.clone(),
+            name: assignment_group.name.clone(),
+            samples: *stats.category_samples.get(&category).unwrap_or(&0),
+        });
+    }
+    labels.sort_by_key(|label| label.category);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn maps_all_groups_with_category() {
+        let created =
+            NaiveDateTime::parse_from_str("2021-01-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let assignment_groups = vec![
+            AssignmentGroup {
+                sys_id: "g2".to_string(),
+                name: "Network".to_string(),
+                sys_created_on: created,
+            },
+            AssignmentGroup {
+                sys_id: "g1".to_string(),
+                name: "Service Desk".to_string(),
+                sys_created_on: created,
+            },
+            AssignmentGroup {
+                sys_id: "g3".to_string(),
+                name: "Unmapped".to_string(),
+                sys_created_on: created,
+            },
+        ];
+        let assignment_groups_indices =
+            HashMap::from([("g1".to_string(), 0), ("g2".to_string(), 1)]);
+        let group_labels = HashMap::from([(
+            "g1".to_string(),
+            GroupLabel {
+                category: 0,
+                name: "Service Desk".to_string(),
+                label: "service-desk".to_string(),
+            },
+        )]);
+        let key = "Service Desk".to_string();
+        let mut stats = Stats::new();
+        stats.inc_distribution(&key, 0);
+        stats.inc_distribution(&key, 0);
+        let labels = map_sidecar_labels(
+            &assignment_groups_indices,
+            &assignment_groups,
+            &group_labels,
+            &stats,
+        );
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].category, 0);
+        assert_eq!(labels[0].sys_id, "g1");
+        assert_eq!(labels[0].label.as_deref(), Some("service-desk"));
+        assert_eq!(labels[0].samples, 2);
+        assert_eq!(labels[1].category, 1);
+        assert_eq!(labels[1].sys_id, "g2");
+        assert_eq!(labels[1].label, None);
+        assert_eq!(labels[1].samples, 0);
+    }
+}
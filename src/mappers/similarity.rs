@@ -0,0 +1,165 @@
+/// Normalization and similarity of incident titles, to de-dupe near-identical titles (e.g. templated monitoring alerts)
+use crate::mappers::normalize::{Normalization, Normalizer};
+use std::collections::{HashMap, HashSet};
+
+/// Amount of consecutive tokens that form a shingle
+const SHINGLE_SIZE: usize = 2;
+/// Steps of the normalization, that make near-identical titles equal (see [Normalization])
+const SIMILARITY_STEPS: [Normalization; 2] = [Normalization::Lowercase, Normalization::Digits];
+
+/// Normalizes a title, so that titles which differ only by case, whitespace, punctuation or numbers (e.g. ticket
+/// numbers, hostnames or timestamps) become equal.
+///
+/// The title is normalized with the [similarity steps][SIMILARITY_STEPS] and split into words at every character
+/// that is neither a letter nor a digit (`srv-0123` becomes `srv 0`).
+///
+/// # Arguments
+///
+/// - `normalizer`: Normalizer with the similarity steps
+/// - `title`: Title of an incident
+///
+/// # Returns
+///
+/// The normalized title
+fn normalize_title(normalizer: &Normalizer, title: &str) -> String {
+    normalizer
+        .normalize(title)
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Creates the shingles (sequences of consecutive tokens) of a normalized title
+///
+/// # Arguments
+///
+/// - `normalized_title`: Normalized title of an incident
+///
+/// # Returns
+///
+/// The shingles of the title. Titles with less tokens than a shingle consist of a single shingle
+fn shingles(normalized_title: &str) -> HashSet<String> {
+    let tokens: Vec<&str> = normalized_title.split(' ').collect();
+    if tokens.len() <= SHINGLE_SIZE {
+        return HashSet::from([tokens.join(" ")]);
+    }
+    tokens
+        .windows(SHINGLE_SIZE)
+        .map(|window| window.join(" "))
+        .collect()
+}
+
+/// Clusters near-identical titles.
+///
+/// Titles are normalized and compared by the Jaccard similarity of their shingles. Every title is compared to the
+/// first title of each existing cluster and is added to the most similar cluster whose similarity reaches the
+/// threshold. Otherwise, it starts a new cluster. The result only depends on the order of the titles.
+///
+/// # Arguments
+///
+/// - `titles`: Distinct titles of the incidents
+/// - `threshold`: Minimum similarity (`0` to `1`) for titles to be considered duplicates
+///
+/// # Returns
+///
+/// The index of the cluster for every title, in the order of the titles
+pub fn cluster_titles(titles: &[&str], threshold: f64) -> Vec<usize> {
+    let mut clusters: Vec<usize> = Vec::with_capacity(titles.len());
+    // Shingles of the first title of each cluster
+    let mut representatives: Vec<HashSet<String>> = Vec::new();
+    let mut normalized_clusters: HashMap<String, usize> = HashMap::new();
+    let mut shingle_index: HashMap<String, Vec<usize>> = HashMap::new();
+    let normalizer = Normalizer::new(&SIMILARITY_STEPS);
+    for title in titles {
+        let normalized_title = normalize_title(&normalizer, title);
+        if let Some(cluster) = normalized_clusters.get(&normalized_title) {
+            clusters.push(*cluster);
+            continue;
+        }
+        let title_shingles = shingles(&normalized_title);
+        let mut shared_shingles: HashMap<usize, usize> = HashMap::new();
+        for shingle in &title_shingles {
+            for cluster in shingle_index.get(shingle).into_iter().flatten() {
+                *shared_shingles.entry(*cluster).or_insert(0) += 1;
+            }
+        }
+        let mut best_match: Option<(usize, f64)> = None;
+        for (cluster, shared) in shared_shingles {
+            let union = title_shingles.len() + representatives[cluster].len() - shared;
+            let similarity = shared as f64 / union as f64;
+            let is_better = match best_match {
+                Some((best_cluster, best_similarity)) => {
+                    similarity > best_similarity
+                        || (similarity == best_similarity && cluster < best_cluster)
+                }
+                None => true,
+            };
+            if similarity >= threshold && is_better {
+                best_match = Some((cluster, similarity));
+            }
+        }
+        let cluster = match best_match {
+            Some((cluster, _)) => cluster,
+            None => {
+                let cluster = representatives.len();
+                for shingle in &title_shingles {
+                    shingle_index
+                        .entry(shingle.clone())
+                        .or_default()
+                        .push(cluster);
+                }
+                representatives.push(title_shingles);
+                cluster
+            }
+        };
+        normalized_clusters.insert(normalized_title, cluster);
+        clusters.push(cluster);
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_titles_that_differ_by_numbers() {
+        assert_eq!(
+            cluster_titles(
+                &[
+                    "Disk full on srv-0123",
+                    "DISK FULL ON SRV-0456",
+                    "Disk full"
+                ],
+                1.0
+            ),
+            [0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn clusters_titles_from_threshold() {
+        // 3 of 5 shingles are shared
+        let titles = ["Disk full on host alpha", "Disk full on host beta"];
+        assert_eq!(cluster_titles(&titles, 0.6), [0, 0]);
+        assert_eq!(cluster_titles(&titles, 0.61), [0, 1]);
+    }
+
+    #[test]
+    fn compares_titles_with_first_title_of_clusters() {
+        // The third title is similar to the second one (3 of 4 shingles), but not to the first one (2 of 5)
+        assert_eq!(
+            cluster_titles(&["a b c d", "a b c e", "a b c e f"], 0.5),
+            [0, 0, 1]
+        );
+        // Clusters with the same similarity are resolved by their order
+        assert_eq!(cluster_titles(&["a b c", "a b d", "a b"], 0.5), [0, 1, 0]);
+    }
+
+    #[test]
+    fn clusters_empty_titles() {
+        assert_eq!(cluster_titles(&["", "---", "Disk full"], 0.5), [0, 0, 1]);
+        assert!(cluster_titles(&[], 0.5).is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+/// Splits the fine-tuning entries into training, validation and test sets
+use crate::loaders::servicenow::Incident;
+use crate::mappers::output::FineTuningEntry;
+use anyhow::bail;
+use chrono::NaiveDateTime;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::BTreeMap;
+
+/// The fine-tuning entries, split into the different data sets
+pub struct DataSplit {
+    /// Entries used for training
+    pub train: Vec<FineTuningEntry>,
+    /// Entries used for validation during fine-tuning
+    pub validation: Vec<FineTuningEntry>,
+    /// Held-out entries for the own evaluation
+    pub test: Vec<FineTuningEntry>,
+}
+
+/// Splits the fine-tuning entries into training, validation and test sets.
+///
+/// The split is stratified by the label of the entries, so that every assignment group is represented with the same
+/// ratios in all data sets. The amount of entries per set is rounded per assignment group, while every assignment
+/// group keeps at least one training entry. The split is reproducible for the same input and seed.
+///
+/// # Arguments
+///
+/// - `entries`: The mapped fine-tuning entries
+/// - `validation_ratio`: Ratio of entries that are used for validation
+/// - `test_ratio`: Ratio of entries that are held out for testing
+/// - `seed`: Seed for the random number generator
+///
+/// # Returns
+///
+/// The split data sets
+///
+/// # Bails out when
+///
+/// - a ratio is outside of `[0, 1)` or the sum of the ratios is not below `1`
+pub fn split_stratified(
+    entries: Vec<FineTuningEntry>,
+    validation_ratio: f64,
+    test_ratio: f64,
+    seed: u64,
+) -> anyhow::Result<DataSplit> {
+    if !(0.0..1.0).contains(&validation_ratio)
+        || !(0.0..1.0).contains(&test_ratio)
+        || validation_ratio + test_ratio >= 1.0
+    {
+        bail!(
+            "Invalid split ratios (validation: {}, test: {}). They must be within [0, 1) and their sum below 1",
+            validation_ratio,
+            test_ratio
+        )
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    // Ordered map, so that the strata are shuffled in a reproducible order
+    let mut strata: BTreeMap<String, Vec<FineTuningEntry>> = BTreeMap::new();
+    for entry in entries {
+        strata
+            .entry(entry.label().to_string())
+            .or_default()
+            .push(entry);
+    }
+    let mut split = DataSplit {
+        train: Vec::new(),
+        validation: Vec::new(),
+        test: Vec::new(),
+    };
+    for (_, mut stratum) in strata {
+        stratum.shuffle(&mut rng);
+        let amount = stratum.len() as f64;
+        // Keep at least one training entry, so that the model learns every label
+        let max_held_out = stratum.len() - 1;
+        let amount_validation = ((amount * validation_ratio).round() as usize).min(max_held_out);
+        let amount_test =
+            ((amount * test_ratio).round() as usize).min(max_held_out - amount_validation);
+        let mut remaining = stratum.into_iter();
+        split
+            .validation
+            .extend(remaining.by_ref().take(amount_validation));
+        split.test.extend(remaining.by_ref().take(amount_test));
+        split.train.extend(remaining);
+    }
+    // Don't keep the entries grouped by assignment group
+    split.train.shuffle(&mut rng);
+    split.validation.shuffle(&mut rng);
+    split.test.shuffle(&mut rng);
+    Ok(split)
+}
+
+/// Splits the incidents chronologically at a cut-off date, to evaluate a model that was trained on past incidents
+/// with future ones
+///
+/// # Arguments
+///
+/// - `incidents`: The incidents to split
+/// - `cut_off`: Incidents that were opened before this date are considered past, all others future incidents
+///
+/// # Returns
+///
+/// Tuple with:
+///
+/// - the past incidents
+/// - the future incidents
+///
+/// # Bails out when
+///
+/// - an incident has neither an open nor a creation date
+pub fn split_chronologically(
+    incidents: Vec<Incident>,
+    cut_off: NaiveDateTime,
+) -> anyhow::Result<(Vec<Incident>, Vec<Incident>)> {
+    let mut past: Vec<Incident> = Vec::new();
+    let mut future: Vec<Incident> = Vec::new();
+    for incident in incidents {
+        match incident.opened() {
+            Some(opened) if opened < cut_off => past.push(incident),
+            Some(_) => future.push(incident),
+            None => bail!(
+                "Incident '{}' has neither `opened_at` nor `sys_created_on`",
+                &incident.short_description
+            ),
+        }
+    }
+    Ok((past, future))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the given amount of entries per label
+    fn entries(amounts: &[(&str, usize)]) -> Vec<FineTuningEntry> {
+        let mut entries: Vec<FineTuningEntry> = Vec::new();
+        for (label, amount) in amounts {
+            for idx in 0..*amount {
+                entries.push(FineTuningEntry::Completion {
+                    prompt: format!("Incident {} of {}", idx, label),
+                    completion: label.to_string(),
+                });
+            }
+        }
+        entries
+    }
+
+    fn count(entries: &[FineTuningEntry], label: &str) -> usize {
+        entries
+            .iter()
+            .filter(|entry| entry.label() == label)
+            .count()
+    }
+
+    fn prompts(entries: &[FineTuningEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                FineTuningEntry::Completion { prompt, .. } => prompt.clone(),
+                FineTuningEntry::Chat { .. } => String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_each_label_by_ratios() {
+        let split = split_stratified(entries(&[(" 1", 10), (" 2", 20)]), 0.2, 0.1, 42).unwrap();
+        assert_eq!(
+            (count(&split.train, " 1"), count(&split.train, " 2")),
+            (7, 14)
+        );
+        assert_eq!(
+            (
+                count(&split.validation, " 1"),
+                count(&split.validation, " 2")
+            ),
+            (2, 4)
+        );
+        assert_eq!((count(&split.test, " 1"), count(&split.test, " 2")), (1, 2));
+    }
+
+    #[test]
+    fn keeps_single_entries_for_training() {
+        let split = split_stratified(entries(&[(" 1", 1), (" 2", 2)]), 0.5, 0.4, 42).unwrap();
+        assert_eq!(
+            (count(&split.train, " 1"), count(&split.train, " 2")),
+            (1, 1)
+        );
+        assert_eq!(count(&split.validation, " 2"), 1);
+        assert!(split.test.is_empty());
+    }
+
+    #[test]
+    fn splits_reproducibly() {
+        let split = split_stratified(entries(&[(" 1", 10), (" 2", 20)]), 0.2, 0.1, 42).unwrap();
+        let split_again =
+            split_stratified(entries(&[(" 1", 10), (" 2", 20)]), 0.2, 0.1, 42).unwrap();
+        assert_eq!(prompts(&split.train), prompts(&split_again.train));
+        assert_eq!(prompts(&split.validation), prompts(&split_again.validation));
+        assert_eq!(prompts(&split.test), prompts(&split_again.test));
+    }
+
+    #[test]
+    fn rejects_invalid_ratios() {
+        for (validation_ratio, test_ratio) in [(0.5, 0.5), (0.7, 0.4), (1.0, 0.0), (-0.1, 0.1)] {
+            assert!(
+                split_stratified(entries(&[(" 1", 10)]), validation_ratio, test_ratio, 42).is_err()
+            );
+        }
+    }
+
+    fn incident(title: &str, opened_at: Option<&str>, sys_created_on: Option<&str>) -> Incident {
+        let datetime =
+            |value: &str| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap();
+        Incident {
+            sys_id: None,
+            number: None,
+            short_description: title.to_string(),
+            description: None,
+            category: None,
+            subcategory: None,
+            cmdb_ci: None,
+            business_service: None,
+            location: None,
+            assignment_group: "g1".to_string(),
+            assignment_group_name: None,
+            opened_at: opened_at.map(datetime),
+            sys_created_on: sys_created_on.map(datetime),
+        }
+    }
+
+    fn titles(incidents: &[Incident]) -> Vec<&str> {
+        incidents
+            .iter()
+            .map(|incident| incident.short_description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_cut_off_date() {
+        let incidents = vec![
+            incident("before", Some("2021-05-31 23:59:59"), None),
+            incident("at", Some("2021-06-01 00:00:00"), None),
+            incident("after", Some("2021-06-02 08:00:00"), None),
+        ];
+        let cut_off =
+            NaiveDateTime::parse_from_str("2021-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let (past, future) = split_chronologically(incidents, cut_off).unwrap();
+        assert_eq!(titles(&past), vec!["before"]);
+        assert_eq!(titles(&future), vec!["at", "after"]);
+    }
+
+    #[test]
+    fn falls_back_to_creation_date() {
+        let incidents = vec![
+            incident(
+                "opened",
+                Some("2021-07-01 00:00:00"),
+                Some("2021-05-01 00:00:00"),
+            ),
+            incident("created", None, Some("2021-05-01 00:00:00")),
+        ];
+        let cut_off =
+            NaiveDateTime::parse_from_str("2021-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let (past, future) = split_chronologically(incidents, cut_off).unwrap();
+        assert_eq!(titles(&past), vec!["created"]);
+        assert_eq!(titles(&future), vec!["opened"]);
+    }
+
+    #[test]
+    fn bails_without_dates() {
+        let incidents = vec![incident("undated", None, None)];
+        let cut_off =
+            NaiveDateTime::parse_from_str("2021-06-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert!(split_chronologically(incidents, cut_off).is_err());
+    }
+}
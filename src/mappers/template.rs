@@ -0,0 +1,411 @@
+/// Templates for the prompts and completions of the fine-tuning entries
+use crate::loaders::servicenow::{Incident, TEXT_FIELDS};
+use crate::mappers::output::{EntryFormat, FineTuningEntry};
+use anyhow::{bail, Result};
+
+/// Prompt template of completion entries: the prompt, followed by the separator
+pub const DEFAULT_PROMPT_TEMPLATE: &str = "{{prompt}}\n\n###\n\n";
+/// Completion template of completion entries: the label with a leading space
+pub const DEFAULT_COMPLETION_TEMPLATE: &str = " {{label}}";
+/// Prompt template of chat entries. Chat models don't need separators or stop sequences, as the roles delimit the
+/// content
+pub const DEFAULT_CHAT_PROMPT_TEMPLATE: &str = "{{prompt}}";
+/// Completion template of chat entries
+pub const DEFAULT_CHAT_COMPLETION_TEMPLATE: &str = "{{label}}";
+
+/// Variables that can be used in templates, besides the text fields of the incidents
+const VARIABLES: [&str; 5] = ["prompt", "category", "label", "group.name", "group.sys_id"];
+/// Prefix of variables, that refer to a text field of the incident (e.g. `incident.category`)
+const INCIDENT_PREFIX: &str = "incident.";
+
+/// A parsed template
+#[derive(Debug)]
+pub struct Template {
+    /// Literal text and placeholders, in the order of the template
+    parts: Vec<TemplatePart>,
+}
+
+/// Part of a template
+#[derive(Debug)]
+enum TemplatePart {
+    /// Literal text
+    Text(String),
+    /// Placeholder (`{{variable}}`), which is replaced by the value of the variable
+    Variable(String),
+}
+
+/// Templates of the prompts and completions
+#[derive(Debug)]
+pub struct EntryTemplates {
+    /// Template of the prompts (or the user message of chat entries)
+    pub prompt: Template,
+    /// Template of the completions (or the assistant message of chat entries)
+    pub completion: Template,
+}
+
+/// Values of the variables of a single entry
+pub struct TemplateValues<'a> {
+    /// The prompt, built from the prompt fields
+    pub prompt: &'a str,
+    /// The category ID
+    pub category: usize,
+    /// Label of the assignment group (see [LabelMode][crate::mappers::label::LabelMode])
+    pub label: &'a str,
+    /// Name of the assignment group
+    pub group_name: &'a str,
+    /// Internal ID of the assignment group
+    pub group_sys_id: &'a str,
+    /// The incident
+    pub incident: &'a Incident,
+}
+
+impl Template {
+    /// Parses a template. Placeholders are written as `{{variable}}`, with optional whitespace around the variable.
+    ///
+    /// Known variables are `prompt`, `category` (the category ID), `label` (the label of the group), `group.name`,
+    /// `group.sys_id` and the text fields of the incidents (e.g. `short_description`). Text fields can be prefixed with
+    /// `incident.` as well, which is required for the `category` field of the incident (`incident.category`).
+    ///
+    /// # Arguments
+    ///
+    /// - `template_raw`: The template
+    ///
+    /// # Bails out when
+    ///
+    /// - a placeholder isn't closed
+    /// - a placeholder contains an unknown variable
+    pub fn parse(template_raw: &str) -> Result<Self> {
+        let mut parts: Vec<TemplatePart> = Vec::new();
+        let mut rest = template_raw;
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start + 2..].find("}}") {
+                Some(end) => start + 2 + end,
+                None => bail!("Unclosed placeholder in template '{}'", template_raw),
+            };
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+            let variable = rest[start + 2..end].trim();
+            if !is_known_variable(variable) {
+                bail!(
+                    "Unknown variable '{}' in template '{}'. Known variables are: {}, {}",
+                    variable,
+                    template_raw,
+                    VARIABLES.join(", "),
+                    TEXT_FIELDS
+                        .iter()
+                        .map(|field| format!("{}{}", INCIDENT_PREFIX, field))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+            parts.push(TemplatePart::Variable(variable.to_string()));
+            rest = &rest[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Renders the template. Variables without value are replaced by an empty string
+    ///
+    /// # Arguments
+    ///
+    /// - `values`: Values of the variables
+    ///
+    /// # Returns
+    ///
+    /// The rendered template
+    pub fn render(&self, values: &TemplateValues) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => rendered.push_str(text),
+                TemplatePart::Variable(variable) => match variable.as_str() {
+                    "prompt" => rendered.push_str(values.prompt),
+                    "category" => rendered.push_str(&values.category.to_string()),
+                    "label" => rendered.push_str(values.label),
+                    "group.name" => rendered.push_str(values.group_name),
+                    "group.sys_id" => rendered.push_str(values.group_sys_id),
+                    field => {
+                        let field = field.strip_prefix(INCIDENT_PREFIX).unwrap_or(field);
+                        rendered.push_str(values.incident.text_field(field).unwrap_or_default());
+                    }
+                },
+            }
+        }
+        rendered
+    }
+
+    /// Checks, whether the template contains a placeholder of a variable
+    ///
+    /// # Arguments
+    ///
+    /// - `variable`: Name of the variable (e.g. `prompt`)
+    ///
+    /// # Returns
+    ///
+    /// `true`, if the variable is used at least once
+    pub fn contains_variable(&self, variable: &str) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Variable(name) if name == variable))
+    }
+
+    /// Gets the literal text after the last placeholder, which acts as separator of prompts and as stop sequence of
+    /// completions
+    ///
+    /// # Returns
+    ///
+    /// The literal text at the end of the template. Empty, if the template ends with a placeholder
+    pub fn suffix(&self) -> &str {
+        match self.parts.last() {
+            Some(TemplatePart::Text(text)) if self.parts.len() > 1 => text,
+            _ => "",
+        }
+    }
+}
+
+impl EntryTemplates {
+    /// Parses the templates of the prompts and completions
+    ///
+    /// # Arguments
+    ///
+    /// - `prompt`: Template of the prompts. Falls back to the default template of the format, if not set
+    /// - `completion`: Template of the completions. Falls back to the default template of the format, if not set
+    /// - `stop_sequence`: Stop sequence, that is appended to the completion template of completion entries
+    /// - `format`: Format of the entries
+    ///
+    /// # Bails out when
+    ///
+    /// - a template is invalid
+    /// - a stop sequence is given for chat entries
+    pub fn parse(
+        prompt: Option<&str>,
+        completion: Option<&str>,
+        stop_sequence: Option<&str>,
+        format: EntryFormat,
+    ) -> Result<Self> {
+        let (default_prompt, default_completion) = match format {
+            EntryFormat::Completion => (DEFAULT_PROMPT_TEMPLATE, DEFAULT_COMPLETION_TEMPLATE),
+            EntryFormat::Chat => (
+                DEFAULT_CHAT_PROMPT_TEMPLATE,
+                DEFAULT_CHAT_COMPLETION_TEMPLATE,
+            ),
+        };
+        let completion = completion.unwrap_or(default_completion);
+        let completion = match (stop_sequence, format) {
+            (Some(_), EntryFormat::Chat) => {
+                bail!("Chat entries don't need a stop sequence, as the roles delimit the content")
+            }
+            (Some(stop_sequence), EntryFormat::Completion) => {
+                format!("{}{}", completion, stop_sequence)
+            }
+            (None, _) => completion.to_string(),
+        };
+        Ok(EntryTemplates {
+            prompt: Template::parse(prompt.unwrap_or(default_prompt))?,
+            completion: Template::parse(&completion)?,
+        })
+    }
+}
+
+/// Checks, that the separator and the stop sequence are consistent across all completion entries.
+///
+/// The separator (the literal end of the prompt template) must not be empty and must only occur at the end of every
+/// prompt, as the model wouldn't know where the prompt ends otherwise. Likewise, the stop sequence (the literal end of
+/// the completion template) must only occur at the end of every completion. Chat entries aren't checked.
+///
+/// # Arguments
+///
+/// - `entries`: The fine-tuning entries
+/// - `templates`: Templates that were used to create the entries
+///
+/// # Bails out when
+///
+/// - the prompt template doesn't end with a separator
+/// - the separator occurs within a prompt
+/// - the stop sequence occurs within a completion
+/// - a completion is empty
+pub fn validate_entries(entries: &[FineTuningEntry], templates: &EntryTemplates) -> Result<()> {
+    let separator = templates.prompt.suffix();
+    let stop_sequence = templates.completion.suffix();
+    for (idx, entry) in entries.iter().enumerate() {
+        let (prompt, completion) = match entry {
+            FineTuningEntry::Completion { prompt, completion } => (prompt, completion),
+            FineTuningEntry::Chat { .. } => continue,
+        };
+        if separator.is_empty() {
+            bail!("The prompt template has to end with a separator (e.g. '\\n\\n###\\n\\n')")
+        }
+        if prompt.matches(separator).count() != 1 || !prompt.ends_with(separator) {
+            bail!(
+                "The separator {:?} occurs within the prompt of entry {}: {:?}",
+                separator,
+                idx + 1,
+                prompt
+            )
+        }
+        if completion.trim().is_empty() || completion.trim() == stop_sequence.trim() {
+            bail!("The completion of entry {} is empty", idx + 1)
+        }
+        if !stop_sequence.is_empty()
+            && (completion.matches(stop_sequence).count() != 1
+                || !completion.ends_with(stop_sequence))
+        {
+            bail!(
+                "The stop sequence {:?} occurs within the completion of entry {}: {:?}",
+                stop_sequence,
+                idx + 1,
+                completion
+            )
+        }
+    }
+    Ok(())
+}
+
+/// Checks, if a variable can be used in templates
+///
+/// # Arguments
+///
+/// - `variable`: Name of the variable
+///
+/// # Returns
+///
+/// `true`, if the variable is known
+fn is_known_variable(variable: &str) -> bool {
+    if VARIABLES.contains(&variable) {
+        return true;
+    }
+    let field = variable.strip_prefix(INCIDENT_PREFIX).unwrap_or(variable);
+    TEXT_FIELDS.contains(&field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::servicenow::parse_incidents;
+
+    fn completion(prompt: &str, completion: &str) -> FineTuningEntry {
+        FineTuningEntry::Completion {
+            prompt: prompt.to_string(),
+            completion: completion.to_string(),
+        }
+    }
+
+    fn templates(stop_sequence: Option<&str>) -> EntryTemplates {
+        EntryTemplates::parse(None, None, stop_sequence, EntryFormat::Completion).unwrap()
+    }
+
+    #[test]
+    fn renders_variables() {
+        let incidents = parse_incidents(
+            r#"{"records": [{
+                "short_description": "Printer jammed",
+                "category": "Hardware",
+                "assignment_group": "hardware"
+            }]}"#,
+        )
+        .unwrap();
+        let values = TemplateValues {
+            prompt: "Printer jammed",
+            category: 3,
+            label: "1",
+            group_name: "Hardware",
+            group_sys_id: "hardware",
+            incident: &incidents[0],
+        };
+        let template = Template::parse(
+            "{{ short_description }}|{{incident.category}}|{{category}}|{{label}}|{{group.name}}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&values),
+            "Printer jammed|Hardware|3|1|Hardware"
+        );
+        let template = Template::parse("{{incident.description}}.").unwrap();
+        assert_eq!(template.render(&values), ".");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let error = Template::parse("{{prompt}} {{title}}").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unknown variable 'title' in template '{{prompt}} {{title}}'"));
+        assert!(Template::parse("{{incident.label}}").is_err());
+        let error = Template::parse("{{prompt}}\n{{label").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unclosed placeholder in template '{{prompt}}\n{{label'"
+        );
+    }
+
+    #[test]
+    fn gets_suffix() {
+        assert_eq!(
+            Template::parse(DEFAULT_PROMPT_TEMPLATE).unwrap().suffix(),
+            "\n\n###\n\n"
+        );
+        assert_eq!(Template::parse("{{prompt}}").unwrap().suffix(), "");
+        assert_eq!(Template::parse("###").unwrap().suffix(), "");
+        assert!(Template::parse("{{ prompt }} ->")
+            .unwrap()
+            .contains_variable("prompt"));
+    }
+
+    #[test]
+    fn validates_separator_and_stop_sequence() {
+        let templates = templates(Some(" END"));
+        assert!(validate_entries(
+            &[completion("Printer jammed\n\n###\n\n", " 1 END")],
+            &templates
+        )
+        .is_ok());
+        let error = validate_entries(
+            &[
+                completion("Printer jammed\n\n###\n\n", " 1 END"),
+                completion("VPN\n\n###\n\ndown\n\n###\n\n", " 2 END"),
+            ],
+            &templates,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("The separator \"\\n\\n###\\n\\n\" occurs within the prompt of entry 2"));
+        let error = validate_entries(
+            &[completion("Printer jammed\n\n###\n\n", " END END")],
+            &templates,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("The stop sequence \" END\" occurs within the completion of entry 1"));
+        let error = validate_entries(
+            &[completion("Printer jammed\n\n###\n\n", " END")],
+            &templates,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "The completion of entry 1 is empty");
+    }
+
+    #[test]
+    fn requires_separator() {
+        let templates =
+            EntryTemplates::parse(Some("{{prompt}}"), None, None, EntryFormat::Completion).unwrap();
+        let error =
+            validate_entries(&[completion("Printer jammed", " 1")], &templates).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The prompt template has to end with a separator (e.g. '\\n\\n###\\n\\n')"
+        );
+        // Chat entries don't need separators
+        let templates = EntryTemplates::parse(None, None, None, EntryFormat::Chat).unwrap();
+        let entries: Vec<FineTuningEntry> = serde_json::from_str(
+            r#"[{"messages": [{"role": "user", "content": "Printer jammed"}, {"role": "assistant", "content": "1"}]}]"#,
+        )
+        .unwrap();
+        assert!(validate_entries(&entries, &templates).is_ok());
+    }
+}
@@ -0,0 +1,291 @@
+/// Counts the tokens of the fine-tuning entries with a byte-level BPE tokenizer (like tiktoken), to keep the prompts
+/// within the limits of the model and to estimate the costs of the training
+use crate::mappers::output::FineTuningEntry;
+use fancy_regex::Regex;
+use std::collections::HashMap;
+
+/// Pre-tokenization pattern of `cl100k_base`
+const PATTERN_CL100K: &str = concat!(
+    r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+",
+    r"|\s+(?!\S)|\s+"
+);
+/// Pre-tokenization pattern of `o200k_base`
+const PATTERN_O200K: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+"
+);
+/// Tokens that are added to each message of chat entries, for the role and the delimiters
+const TOKENS_PER_MESSAGE: usize = 3;
+/// Tokens that are added to each chat entry, for priming the reply
+const TOKENS_PER_CHAT: usize = 3;
+
+/// Encoding of a vocabulary, which determines how texts are split into words before the BPE merges
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// `cl100k_base` (GPT-3.5 and GPT-4)
+    Cl100k,
+    /// `o200k_base` (GPT-4o and newer)
+    O200k,
+}
+
+/// Options to count the tokens of the fine-tuning entries
+pub struct TokenOptions {
+    /// Filepath to the BPE vocabulary. Tokens are only counted, if it is set
+    pub file_vocabulary: Option<String>,
+    /// Encoding of the vocabulary
+    pub encoding: Encoding,
+    /// Amount of epochs the model is trained for
+    pub epochs: u32,
+    /// Price per 1M training tokens, to estimate the costs of the training
+    pub price: Option<f64>,
+}
+
+/// Byte-level BPE tokenizer
+pub struct Tokenizer {
+    /// Rank of each token of the vocabulary
+    ranks: HashMap<Vec<u8>, u32>,
+    /// Pattern that splits texts into words, which are encoded separately
+    pattern: Regex,
+}
+
+impl Tokenizer {
+    /// Creates a tokenizer for a vocabulary
+    ///
+    /// # Arguments
+    ///
+    /// - `ranks`: Rank of each token of the vocabulary (see
+    ///   [parse_vocabulary][crate::loaders::vocabulary::parse_vocabulary])
+    /// - `encoding`: Encoding of the vocabulary
+    pub fn new(ranks: HashMap<Vec<u8>, u32>, encoding: Encoding) -> Self {
+        let pattern = match encoding {
+            Encoding::Cl100k => PATTERN_CL100K,
+            Encoding::O200k => PATTERN_O200K,
+        };
+        Tokenizer {
+            ranks,
+            // The pre-tokenization patterns of tiktoken are fixed and always compile
+            pattern: Regex::new(pattern).unwrap(),
+        }
+    }
+
+    /// Counts the tokens of a text
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    ///
+    /// # Returns
+    ///
+    /// The amount of tokens
+    pub fn count(&self, text: &str) -> usize {
+        self.token_ends(text).len()
+    }
+
+    /// Counts the tokens of a fine-tuning entry. For chat entries, the tokens of the roles and delimiters are added
+    ///
+    /// # Arguments
+    ///
+    /// - `entry`: The fine-tuning entry
+    ///
+    /// # Returns
+    ///
+    /// The amount of tokens
+    pub fn count_entry(&self, entry: &FineTuningEntry) -> usize {
+        match entry {
+            FineTuningEntry::Completion { prompt, completion } => {
+                self.count(prompt) + self.count(completion)
+            }
+            FineTuningEntry::Chat { messages } => {
+                messages
+                    .iter()
+                    .map(|message| self.count(&message.content) + TOKENS_PER_MESSAGE)
+                    .sum::<usize>()
+                    + TOKENS_PER_CHAT
+            }
+        }
+    }
+
+    /// Truncates a text to a maximum amount of tokens
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    /// - `max_tokens`: Maximum amount of tokens
+    ///
+    /// # Returns
+    ///
+    /// The first `max_tokens` tokens of the text. Tokens that end within a character are dropped
+    pub fn truncate<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        let token_ends = self.token_ends(text);
+        if token_ends.len() <= max_tokens {
+            return text;
+        }
+        if max_tokens == 0 {
+            return "";
+        }
+        let mut end = token_ends[max_tokens - 1];
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        &text[..end]
+    }
+
+    /// Encodes a text and gets the position at which each token ends
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text
+    ///
+    /// # Returns
+    ///
+    /// The (byte) position within the text, after each token
+    fn token_ends(&self, text: &str) -> Vec<usize> {
+        let mut token_ends: Vec<usize> = Vec::new();
+        let mut start: usize = 0;
+        for word in self.pattern.find_iter(text) {
+            // The pattern only fails, if its backtrack limit is exceeded. The rest is encoded as a whole then
+            let word = match word {
+                Ok(word) => word,
+                Err(_) => break,
+            };
+            let bytes = text.as_bytes();
+            self.encode_word(&bytes[start..word.start()], start, &mut token_ends);
+            self.encode_word(
+                &bytes[word.start()..word.end()],
+                word.start(),
+                &mut token_ends,
+            );
+            start = word.end();
+        }
+        self.encode_word(&text.as_bytes()[start..], start, &mut token_ends);
+        token_ends
+    }
+
+    /// Encodes a single word by merging its bytes according to the ranks of the vocabulary. The pair with the lowest
+    /// rank is merged first, until no pair of the word is a token anymore
+    ///
+    /// # Arguments
+    ///
+    /// - `word`: Bytes of the word
+    /// - `offset`: Position of the word within the text
+    /// - `token_ends`: The position after each token of the word is added to this list
+    fn encode_word(&self, word: &[u8], offset: usize, token_ends: &mut Vec<usize>) {
+        if word.is_empty() {
+            return;
+        }
+        if self.ranks.contains_key(word) {
+            token_ends.push(offset + word.len());
+            return;
+        }
+        // Start of each part and the rank of the pair, that starts with the part
+        let mut parts: Vec<(usize, u32)> = (0..=word.len()).map(|idx| (idx, u32::MAX)).collect();
+        for idx in 0..parts.len() {
+            parts[idx].1 = self.pair_rank(word, &parts, idx);
+        }
+        // The last part has no pair, so it is never the minimum
+        while let Some((idx, (_, rank))) = parts[..parts.len() - 1]
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, rank))| *rank)
+        {
+            if *rank == u32::MAX {
+                break;
+            }
+            parts.remove(idx + 1);
+            parts[idx].1 = self.pair_rank(word, &parts, idx);
+            if idx > 0 {
+                parts[idx - 1].1 = self.pair_rank(word, &parts, idx - 1);
+            }
+        }
+        for (end, _) in &parts[1..] {
+            token_ends.push(offset + end);
+        }
+    }
+
+    /// Gets the rank of the pair of parts, that starts with the given part
+    ///
+    /// # Arguments
+    ///
+    /// - `word`: Bytes of the word
+    /// - `parts`: Start of each part
+    /// - `idx`: Index of the first part of the pair
+    ///
+    /// # Returns
+    ///
+    /// The rank of the merged pair. `u32::MAX`, if it isn't a token or there is no second part
+    fn pair_rank(&self, word: &[u8], parts: &[(usize, u32)], idx: usize) -> u32 {
+        if idx + 2 >= parts.len() {
+            return u32::MAX;
+        }
+        *self
+            .ranks
+            .get(&word[parts[idx].0..parts[idx + 2].0])
+            .unwrap_or(&u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenizer(tokens: &[(&str, u32)]) -> Tokenizer {
+        let ranks = tokens
+            .iter()
+            .map(|(token, rank)| (token.as_bytes().to_vec(), *rank))
+            .collect();
+        Tokenizer::new(ranks, Encoding::Cl100k)
+    }
+
+    fn encode_word(tokenizer: &Tokenizer, word: &str) -> Vec<usize> {
+        let mut token_ends: Vec<usize> = Vec::new();
+        tokenizer.encode_word(word.as_bytes(), 0, &mut token_ends);
+        token_ends
+    }
+
+    #[test]
+    fn merges_pairs_by_rank() {
+        assert_eq!(
+            encode_word(&tokenizer(&[("ab", 0), ("bc", 1)]), "abc"),
+            [2, 3]
+        );
+        assert_eq!(
+            encode_word(&tokenizer(&[("ab", 1), ("bc", 0)]), "abc"),
+            [1, 3]
+        );
+        assert_eq!(
+            encode_word(&tokenizer(&[("ab", 0), ("bc", 1), ("abc", 2)]), "abcd"),
+            [3, 4]
+        );
+        // A pair is only merged, if both parts are tokens
+        assert_eq!(
+            encode_word(&tokenizer(&[("ab", 0), ("abc", 1)]), "bcab"),
+            [1, 2, 4]
+        );
+    }
+
+    #[test]
+    fn merges_first_pair_of_same_rank() {
+        assert_eq!(encode_word(&tokenizer(&[("aa", 0)]), "aaa"), [2, 3]);
+        assert_eq!(encode_word(&tokenizer(&[("aa", 0)]), "aaaaa"), [2, 4, 5]);
+    }
+
+    #[test]
+    fn counts_tokens_of_words() {
+        // Words of the vocabulary are tokens as a whole, others are split into their bytes
+        let tokenizer = tokenizer(&[("Printer", 0), (" jammed", 1)]);
+        assert_eq!(tokenizer.count("Printer jammed"), 2);
+        assert_eq!(tokenizer.count("Printer jams"), 6);
+        assert_eq!(tokenizer.count(""), 0);
+    }
+
+    #[test]
+    fn truncates_at_char_boundaries() {
+        let tokenizer = tokenizer(&[("ab", 0)]);
+        // `é` consists of two bytes, which aren't tokens of the vocabulary
+        assert_eq!(tokenizer.truncate("abé", 3), "abé");
+        assert_eq!(tokenizer.truncate("abé", 2), "ab");
+        assert_eq!(tokenizer.truncate("abé", 1), "ab");
+        assert_eq!(tokenizer.truncate("abé", 0), "");
+    }
+}
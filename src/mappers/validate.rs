@@ -0,0 +1,610 @@
+/// Checks fine-tuning entries for common mistakes, like the `fine_tunes.prepare_data` tool of OpenAI does
+use crate::mappers::output::{ChatRole, FineTuningEntry};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Entries are considered to be a classification, if there are at least this many entries per distinct label
+const CLASSIFICATION_THRESHOLD: usize = 3;
+/// Recommended minimum amount of examples per class
+const MIN_EXAMPLES_PER_CLASS: usize = 100;
+/// Maximum amount of entries (or classes), that are listed in a finding
+const MAX_LISTED: usize = 5;
+
+/// Severity of a finding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The entries can be used, but the results might be worse than necessary
+    Warning,
+    /// The entries can't be used as they are
+    Error,
+}
+
+/// A single finding of the validation
+#[derive(Debug)]
+pub struct Finding {
+    /// Severity of the finding
+    pub severity: Severity,
+    /// Description of the finding
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "Warning: {}", self.message),
+            Severity::Error => write!(f, "Error: {}", self.message),
+        }
+    }
+}
+
+/// Result of the validation of fine-tuning entries
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Amount of validated entries
+    pub entries: usize,
+    /// Amount of distinct labels (completions or assistant messages)
+    pub classes: usize,
+    /// `true`, if the entries look like a classification
+    pub is_classification: bool,
+    /// All findings, in the order they were found
+    pub findings: Vec<Finding>,
+}
+
+impl ValidationReport {
+    /// Counts the findings with the given severity
+    ///
+    /// # Arguments
+    ///
+    /// - `severity`: The severity
+    ///
+    /// # Returns
+    ///
+    /// The amount of findings
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+
+    /// Prints the summary and all findings
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: Name of the validated entries (e.g. the filepath)
+    pub fn print(&self, name: &str) {
+        let task = match self.is_classification {
+            true => "classification",
+            false => "generation",
+        };
+        println!(
+            "{}: {} entries, {} distinct labels ({})",
+            name, self.entries, self.classes, task
+        );
+        for finding in &self.findings {
+            println!("  {}", finding);
+        }
+        println!(
+            "  {} error(s), {} warning(s)",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        );
+    }
+
+    /// Adds a warning
+    fn warn(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    /// Adds an error
+    fn error(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        });
+    }
+}
+
+/// Validates fine-tuning entries. Following checks are done:
+///
+/// - All entries have the same format (`completion` or `chat`)
+/// - No prompt, completion or message is empty, and every chat entry ends with an assistant message
+/// - The prompts end with a common separator, which doesn't occur anywhere else within the prompts (error)
+/// - The prompts don't start with a common prefix (warning)
+/// - The completions start with a whitespace character and don't share a common prefix beyond that (warning)
+/// - The stop sequence doesn't occur within the completions (error) and is present for generation tasks (warning)
+/// - There are no duplicate entries (warning)
+/// - There are at least two classes (error) with enough examples each (warning), for classification tasks
+///
+/// # Arguments
+///
+/// - `entries`: The fine-tuning entries
+///
+/// # Returns
+///
+/// The report with all findings
+pub fn validate_entries(entries: &[FineTuningEntry]) -> ValidationReport {
+    let mut report = ValidationReport {
+        entries: entries.len(),
+        ..Default::default()
+    };
+    if entries.is_empty() {
+        report.error("There are no entries".to_string());
+        return report;
+    }
+    let mut completions: Vec<(&str, &str)> = Vec::new();
+    let mut chats: Vec<usize> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        match entry {
+            FineTuningEntry::Completion { prompt, completion } => {
+                completions.push((prompt, completion))
+            }
+            FineTuningEntry::Chat { .. } => chats.push(idx),
+        }
+    }
+    if !completions.is_empty() && !chats.is_empty() {
+        report.error(format!(
+            "Completion and chat entries are mixed (chat {})",
+            format_entries(&chats)
+        ));
+        return report;
+    }
+    let labels: Vec<&str> = entries.iter().map(|entry| entry.label()).collect();
+    validate_classes(&labels, &mut report);
+    match chats.is_empty() {
+        true => validate_completion_entries(&completions, &mut report),
+        false => validate_chat_entries(entries, &mut report),
+    }
+    validate_duplicates(entries, &mut report);
+    report
+}
+
+/// Validates the entries of the legacy completion format
+///
+/// # Arguments
+///
+/// - `entries`: Prompt and completion of each entry
+/// - `report`: Report, that receives the findings
+fn validate_completion_entries(entries: &[(&str, &str)], report: &mut ValidationReport) {
+    let prompts: Vec<&str> = entries.iter().map(|(prompt, _)| *prompt).collect();
+    let completions: Vec<&str> = entries.iter().map(|(_, completion)| *completion).collect();
+    let empty_prompts = find_entries(&prompts, |prompt| prompt.trim().is_empty());
+    if !empty_prompts.is_empty() {
+        report.error(format!(
+            "{} prompts are empty: {}",
+            empty_prompts.len(),
+            format_entries(&empty_prompts)
+        ));
+    }
+    let empty_completions = find_entries(&completions, |completion| completion.trim().is_empty());
+    if !empty_completions.is_empty() {
+        report.error(format!(
+            "{} completions are empty: {}",
+            empty_completions.len(),
+            format_entries(&empty_completions)
+        ));
+    }
+    // Separator
+    let separator = common_suffix(&prompts);
+    if separator.is_empty() {
+        report.error(
+            "The prompts don't end with a common separator (e.g. '\\n\\n###\\n\\n'), so the model can't tell where \
+             the prompt ends"
+                .to_string(),
+        );
+    } else {
+        let within = find_entries(&prompts, |prompt| prompt.matches(separator).count() > 1);
+        if !within.is_empty() {
+            report.error(format!(
+                "The separator {:?} occurs within {} prompts: {}",
+                separator,
+                within.len(),
+                format_entries(&within)
+            ));
+        }
+    }
+    if prompts.len() > 1 {
+        let prefix = common_prefix(&prompts);
+        if !prefix.is_empty() {
+            report.warn(format!(
+                "All prompts start with {:?}, which doesn't carry any information and can be removed",
+                prefix
+            ));
+        }
+    }
+    // Completions
+    let no_whitespace = find_entries(&completions, |completion| {
+        !completion.starts_with(char::is_whitespace)
+    });
+    if !no_whitespace.is_empty() {
+        report.warn(format!(
+            "{} completions don't start with a whitespace character, which tokenizes better (e.g. ' 1' instead of \
+             '1'): {}",
+            no_whitespace.len(),
+            format_entries(&no_whitespace)
+        ));
+    }
+    if report.classes > 1 {
+        let prefix = common_prefix(&completions).trim_start();
+        if !prefix.is_empty() {
+            report.warn(format!(
+                "All completions start with {:?}, which doesn't carry any information and can be removed",
+                prefix
+            ));
+        }
+    }
+    // Stop sequence
+    let stop_sequence = common_suffix(&completions);
+    if stop_sequence.trim().is_empty() {
+        if !report.is_classification {
+            report.warn(
+                "The completions don't end with a common stop sequence (e.g. ' END'), so the model doesn't know when \
+                 to stop generating"
+                    .to_string(),
+            );
+        }
+    } else {
+        let within = find_entries(&completions, |completion| {
+            completion.matches(stop_sequence).count() > 1
+        });
+        if !within.is_empty() {
+            report.error(format!(
+                "The stop sequence {:?} occurs within {} completions: {}",
+                stop_sequence,
+                within.len(),
+                format_entries(&within)
+            ));
+        }
+    }
+}
+
+/// Validates the entries of the chat format
+///
+/// # Arguments
+///
+/// - `entries`: The fine-tuning entries
+/// - `report`: Report, that receives the findings
+fn validate_chat_entries(entries: &[FineTuningEntry], report: &mut ValidationReport) {
+    let mut empty_messages: Vec<usize> = Vec::new();
+    let mut no_reply: Vec<usize> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        let messages = match entry {
+            FineTuningEntry::Chat { messages } => messages,
+            FineTuningEntry::Completion { .. } => continue,
+        };
+        if messages
+            .iter()
+            .any(|message| message.content.trim().is_empty())
+        {
+            empty_messages.push(idx);
+        }
+        if messages.last().map(|message| message.role) != Some(ChatRole::Assistant) {
+            no_reply.push(idx);
+        }
+    }
+    if !empty_messages.is_empty() {
+        report.error(format!(
+            "{} entries contain empty messages: {}",
+            empty_messages.len(),
+            format_entries(&empty_messages)
+        ));
+    }
+    if !no_reply.is_empty() {
+        report.error(format!(
+            "{} entries don't end with an assistant message: {}",
+            no_reply.len(),
+            format_entries(&no_reply)
+        ));
+    }
+}
+
+/// Counts the distinct labels and checks the amount of examples per class, if the entries look like a classification
+///
+/// # Arguments
+///
+/// - `labels`: Label of each entry
+/// - `report`: Report, that receives the amount of classes and the findings
+fn validate_classes(labels: &[&str], report: &mut ValidationReport) {
+    let mut classes: BTreeMap<&str, usize> = BTreeMap::new();
+    for label in labels {
+        *classes.entry(label.trim()).or_default() += 1;
+    }
+    report.classes = classes.len();
+    report.is_classification = classes.len() * CLASSIFICATION_THRESHOLD <= labels.len();
+    if !report.is_classification {
+        return;
+    }
+    if classes.len() < 2 {
+        report.error(format!(
+            "All entries have the same label {:?}, so there is nothing to classify",
+            labels[0]
+        ));
+        return;
+    }
+    let small_classes: Vec<String> = classes
+        .iter()
+        .filter(|(_, amount)| **amount < MIN_EXAMPLES_PER_CLASS)
+        .map(|(label, amount)| format!("{:?} ({})", label, amount))
+        .collect();
+    if !small_classes.is_empty() {
+        report.warn(format!(
+            "{} of {} classes have less than {} examples: {}",
+            small_classes.len(),
+            classes.len(),
+            MIN_EXAMPLES_PER_CLASS,
+            format_list(&small_classes)
+        ));
+    }
+}
+
+/// Checks, that no entry occurs more than once
+///
+/// # Arguments
+///
+/// - `entries`: The fine-tuning entries
+/// - `report`: Report, that receives the findings
+fn validate_duplicates(entries: &[FineTuningEntry], report: &mut ValidationReport) {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut duplicates: Vec<usize> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        // Entries are compared by their serialization, as they can't be hashed
+        if !seen.insert(serde_json::to_string(entry).unwrap_or_default()) {
+            duplicates.push(idx);
+        }
+    }
+    if !duplicates.is_empty() {
+        report.warn(format!(
+            "{} entries are duplicates of previous entries: {}",
+            duplicates.len(),
+            format_entries(&duplicates)
+        ));
+    }
+}
+
+/// Finds the entries, whose text matches a predicate
+///
+/// # Arguments
+///
+/// - `texts`: Text of each entry
+/// - `predicate`: The predicate
+///
+/// # Returns
+///
+/// The indices of the matching entries
+fn find_entries(texts: &[&str], predicate: impl Fn(&str) -> bool) -> Vec<usize> {
+    texts
+        .iter()
+        .enumerate()
+        .filter(|(_, text)| predicate(text))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Determines the longest common prefix of all texts
+///
+/// # Arguments
+///
+/// - `texts`: The texts
+///
+/// # Returns
+///
+/// The common prefix. Empty, if there is none
+fn common_prefix<'a>(texts: &[&'a str]) -> &'a str {
+    let mut prefix = texts.first().copied().unwrap_or_default();
+    for text in &texts[1..] {
+        let len = prefix
+            .char_indices()
+            .zip(text.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((idx, _), _)| idx)
+            .unwrap_or_else(|| prefix.len().min(text.len()));
+        prefix = &prefix[..len];
+    }
+    prefix
+}
+
+/// Determines the longest common suffix of all texts, that doesn't start within a word. E.g. the common suffix of ` 1`,
+/// ` 11` and ` 21` is empty, rather than `1`
+///
+/// # Arguments
+///
+/// - `texts`: The texts
+///
+/// # Returns
+///
+/// The common suffix. Empty, if there is none
+fn common_suffix<'a>(texts: &[&'a str]) -> &'a str {
+    let mut suffix = texts.first().copied().unwrap_or_default();
+    for text in &texts[1..] {
+        let len: usize = suffix
+            .chars()
+            .rev()
+            .zip(text.chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        suffix = &suffix[suffix.len() - len..];
+    }
+    let starts_within_word = suffix.starts_with(char::is_alphanumeric)
+        && texts.iter().any(|text| {
+            text[..text.len() - suffix.len()]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+        });
+    match starts_within_word {
+        true => suffix.trim_start_matches(char::is_alphanumeric),
+        false => suffix,
+    }
+}
+
+/// Formats the numbers of the entries (starting at 1), of which only the first few are listed
+///
+/// # Arguments
+///
+/// - `indices`: Indices of the entries
+///
+/// # Returns
+///
+/// The formatted entry numbers (e.g. `entries 1, 4, 5 and 12 more`)
+fn format_entries(indices: &[usize]) -> String {
+    let numbers: Vec<String> = indices.iter().map(|idx| (idx + 1).to_string()).collect();
+    format!("entries {}", format_list(&numbers))
+}
+
+/// Formats a list, of which only the first few items are listed
+///
+/// # Arguments
+///
+/// - `items`: The items
+///
+/// # Returns
+///
+/// The comma-separated items (e.g. `a, b, c and 3 more`)
+fn format_list(items: &[String]) -> String {
+    if items.len() <= MAX_LISTED {
+        return items.join(", ");
+    }
+    format!(
+        "{} and {} more",
+        items[..MAX_LISTED].join(", "),
+        items.len() - MAX_LISTED
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::output::ChatMessage;
+
+    fn completion(prompt: &str, completion: &str) -> FineTuningEntry {
+        FineTuningEntry::Completion {
+            prompt: prompt.to_string(),
+            completion: completion.to_string(),
+        }
+    }
+
+    fn chat(user: &str, assistant: &str) -> FineTuningEntry {
+        FineTuningEntry::Chat {
+            messages: vec![
+                ChatMessage {
+                    role: ChatRole::User,
+                    content: user.to_string(),
+                },
+                ChatMessage {
+                    role: ChatRole::Assistant,
+                    content: assistant.to_string(),
+                },
+            ],
+        }
+    }
+
+    fn findings(report: &ValidationReport, severity: Severity) -> Vec<&str> {
+        report
+            .findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ignores_common_suffix_within_labels() {
+        let report = validate_entries(&[
+            completion("Printer jammed\n\n###\n\n", " 1"),
+            completion("VPN down\n\n###\n\n", " 11"),
+            completion("Mail bounced\n\n###\n\n", " 21"),
+        ]);
+        assert!(findings(&report, Severity::Error).is_empty());
+        let warnings = findings(&report, Severity::Warning);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("The completions don't end with a common stop sequence"));
+    }
+
+    #[test]
+    fn rejects_mixed_formats() {
+        let report = validate_entries(&[
+            completion("Printer jammed\n\n###\n\n", " 1"),
+            chat("VPN down", "2"),
+        ]);
+        assert_eq!(
+            findings(&report, Severity::Error),
+            ["Completion and chat entries are mixed (chat entries 2)"]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_separator() {
+        let report = validate_entries(&[
+            completion("Printer jammed", " 1"),
+            completion("VPN down", " 2"),
+        ]);
+        let errors = findings(&report, Severity::Error);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("The prompts don't end with a common separator"));
+    }
+
+    #[test]
+    fn rejects_separator_within_prompts() {
+        let report = validate_entries(&[
+            completion("Printer jammed\n\n###\n\n", " 1"),
+            completion("VPN\n\n###\n\ndown\n\n###\n\n", " 2"),
+        ]);
+        assert_eq!(
+            findings(&report, Severity::Error),
+            ["The separator \"\\n\\n###\\n\\n\" occurs within 1 prompts: entries 2"]
+        );
+    }
+
+    #[test]
+    fn rejects_stop_sequence_within_completions() {
+        let report = validate_entries(&[
+            completion("Printer jammed ->", " Replace the toner END"),
+            completion("VPN down ->", " Restart the END client END"),
+        ]);
+        assert_eq!(
+            findings(&report, Severity::Error),
+            ["The stop sequence \" END\" occurs within 1 completions: entries 2"]
+        );
+    }
+
+    #[test]
+    fn warns_about_duplicates() {
+        let report = validate_entries(&[
+            chat("Printer jammed", "1"),
+            chat("VPN down", "2"),
+            chat("Printer jammed", "1"),
+        ]);
+        assert!(findings(&report, Severity::Error).is_empty());
+        assert_eq!(
+            findings(&report, Severity::Warning),
+            ["1 entries are duplicates of previous entries: entries 3"]
+        );
+    }
+
+    #[test]
+    fn rejects_single_class() {
+        let report = validate_entries(&[
+            chat("Printer jammed", "1"),
+            chat("VPN down", "1"),
+            chat("Mail bounced", "1"),
+        ]);
+        assert!(report.is_classification);
+        assert_eq!(
+            findings(&report, Severity::Error),
+            ["All entries have the same label \"1\", so there is nothing to classify"]
+        );
+    }
+
+    #[test]
+    fn finds_common_suffix_at_word_boundary() {
+        assert_eq!(common_suffix(&[" 1", " 11", " 21"]), "");
+        assert_eq!(common_suffix(&[" 1 END", " 12 END"]), " END");
+        assert_eq!(common_suffix(&["a ->", "b->"]), "->");
+        assert_eq!(common_suffix(&["xEND", "END"]), "");
+        assert_eq!(common_suffix(&["END", "END"]), "END");
+    }
+}
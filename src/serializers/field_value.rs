@@ -0,0 +1,145 @@
+/// Serde deserializers for fields of the Table API, which are either plain values or objects with the value and the
+/// display value (e.g. with `sysparm_display_value=all`)
+use serde::Deserializer;
+use serde_derive::Deserialize;
+
+/// A field, which is either a plain value or an object of the Table API. For reference fields, the value is the
+/// internal ID of the referenced record and the display value its name. For choice fields and dates, the display value
+/// is localized
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    /// Plain value
+    Plain(String),
+    /// Object with value and display value
+    Object {
+        /// Raw value (e.g. the internal ID of a referenced record)
+        #[serde(default)]
+        value: Option<String>,
+        /// Human readable value (e.g. the name of a referenced record)
+        #[serde(default)]
+        display_value: Option<String>,
+    },
+}
+
+impl FieldValue {
+    /// Gets the raw value, which is used for internal IDs and dates
+    ///
+    /// # Returns
+    ///
+    /// The raw value, falling back to the display value. Empty, if both are missing
+    pub fn into_value(self) -> String {
+        match self {
+            FieldValue::Plain(value) => value,
+            FieldValue::Object {
+                value,
+                display_value,
+            } => non_empty(value).or(display_value).unwrap_or_default(),
+        }
+    }
+
+    /// Gets the human readable value, which is used for texts
+    ///
+    /// # Returns
+    ///
+    /// The display value, falling back to the raw value. Empty, if both are missing
+    pub fn into_display_value(self) -> String {
+        match self {
+            FieldValue::Plain(value) => value,
+            FieldValue::Object {
+                value,
+                display_value,
+            } => non_empty(display_value).or(value).unwrap_or_default(),
+        }
+    }
+}
+
+/// Serde-deserializer for the raw value of a field (see [FieldValue::into_value])
+///
+/// # Arguments
+///
+/// - `deserializer`: The Deserializer
+///
+/// # Returns
+///
+/// The raw value
+///
+/// # Bails out when
+///
+/// - the value is neither a string nor an object of the Table API
+pub fn value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    <FieldValue as serde::Deserialize>::deserialize(deserializer).map(FieldValue::into_value)
+}
+
+/// Serde-deserializer for the optional raw value of a field (see [FieldValue::into_value]). `null` is deserialized to
+/// `None`
+///
+/// # Arguments
+///
+/// - `deserializer`: The Deserializer
+///
+/// # Returns
+///
+/// The raw value, if there is any
+///
+/// # Bails out when
+///
+/// - the value is neither `null`, nor a string, nor an object of the Table API
+pub fn optional_value<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    <Option<FieldValue> as serde::Deserialize>::deserialize(deserializer)
+        .map(|field_value| field_value.map(FieldValue::into_value))
+}
+
+/// Serde-deserializer for the human readable value of a field (see [FieldValue::into_display_value])
+///
+/// # Arguments
+///
+/// - `deserializer`: The Deserializer
+///
+/// # Returns
+///
+/// The display value
+///
+/// # Bails out when
+///
+/// - the value is neither a string nor an object of the Table API
+pub fn display_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    <FieldValue as serde::Deserialize>::deserialize(deserializer)
+        .map(FieldValue::into_display_value)
+}
+
+/// Serde-deserializer for the optional human readable value of a field (see [FieldValue::into_display_value]). `null`
+/// is deserialized to `None`
+///
+/// # Arguments
+///
+/// - `deserializer`: The Deserializer
+///
+/// # Returns
+///
+/// The display value, if there is any
+///
+/// # Bails out when
+///
+/// - the value is neither `null`, nor a string, nor an object of the Table API
+pub fn optional_display_value<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    <Option<FieldValue> as serde::Deserialize>::deserialize(deserializer)
+        .map(|field_value| field_value.map(FieldValue::into_display_value))
+}
+
+/// Filters empty values
+///
+/// # Arguments
+///
+/// - `value`: The value
+///
+/// # Returns
+///
+/// The value, if it isn't empty
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.is_empty())
+}
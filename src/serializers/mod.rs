@@ -1,2 +1,4 @@
 /// (De-)serializers
+pub mod field_value;
 pub mod naive_datetime;
+pub mod optional_naive_datetime;
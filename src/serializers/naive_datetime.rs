@@ -1,9 +1,13 @@
-/// Serde deserializer for ISO 8601 "date and time"s without timezone
+/// Serde (de-)serializer for ISO 8601 "date and time"s without timezone
+use crate::serializers::field_value;
 use chrono::NaiveDateTime;
 use serde::de::Error;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserializer, Serializer};
 
-/// Serde-deserializer for a `NaiveDateTime` field
+/// Format of the "date and time"s, as used by SNOW exports
+const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Serde-deserializer for a `NaiveDateTime` field. For value objects of the Table API, the unlocalized value is read
 ///
 /// # Arguments
 ///
@@ -17,6 +21,24 @@ use serde::{Deserialize, Deserializer};
 ///
 /// - the value can't be deserialized
 pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDateTime, D::Error> {
-    let time: String = Deserialize::deserialize(deserializer)?;
-    NaiveDateTime::parse_from_str(&time, "%Y-%m-%d %H:%M:%S").map_err(Error::custom)
+    let time = field_value::value(deserializer)?;
+    NaiveDateTime::parse_from_str(&time, FORMAT).map_err(Error::custom)
+}
+
+/// Serde-serializer for a `NaiveDateTime` field
+///
+/// # Arguments
+///
+/// - `time`: The value to serialize
+/// - `serializer`: The Serializer
+///
+/// # Returns
+///
+/// The result of the serializer
+///
+/// # Bails out when
+///
+/// - the value can't be serialized
+pub fn serialize<S: Serializer>(time: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.format(FORMAT).to_string())
 }
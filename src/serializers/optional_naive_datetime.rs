@@ -0,0 +1,31 @@
+/// Serde deserializer for optional ISO 8601 "date and time"s without timezone
+use crate::serializers::{field_value, naive_datetime};
+use chrono::NaiveDateTime;
+use serde::de::IntoDeserializer;
+use serde::Deserializer;
+
+/// Serde-deserializer for an `Option<NaiveDateTime>` field. Missing, `null` and empty values are deserialized to `None`
+/// (for value objects of the Table API, the unlocalized value is read)
+///
+/// # Arguments
+///
+/// - `deserializer`: The Deserializer
+///
+/// # Returns
+///
+/// The deserialized `NaiveDateTime`, if there is any
+///
+/// # Bails out when
+///
+/// - the value is not empty, but can't be deserialized
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDateTime>, D::Error> {
+    let time = field_value::optional_value(deserializer)?;
+    match time {
+        Some(time) if !time.is_empty() => {
+            naive_datetime::deserialize(time.into_deserializer()).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
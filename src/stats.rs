@@ -1,22 +1,78 @@
 /// This module keeps and modifies statistics
 use std::cmp;
 use std::collections::HashMap;
+use std::fmt::Display;
 
 /// If the terminal width can't be determined, use this width
 const DEFAULT_MAX_WIDTH_HISTOGRAM: u16 = 80;
 
+/// Amount of occurrences, that is shown in a histogram
+trait Amount: Copy + Ord + Default + Display {
+    /// Converts the amount, to scale the bars of the histogram
+    fn as_f64(self) -> f64;
+}
+
+impl Amount for u16 {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Amount for u32 {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Amount for usize {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
 /// Keeps statistics that were collected during the conversion
 #[derive(Clone)]
 pub struct Stats {
     /// Distribution of keys, with occurrences. The keys consist of the original key with the mapped
     /// OpenAI category appended as suffix ` [ID]` Whereas `ID` is the numeric category-ID
     pub distribution: HashMap<String, u16>,
+    /// Amount of samples per OpenAI category-ID
+    pub category_samples: HashMap<usize, usize>,
+    /// Amount of titles that were assigned to different groups, by the names of these groups (joined by ` <> `)
+    pub title_conflicts: HashMap<String, usize>,
+    /// Amount of values that were redacted, by the name of the redaction rule
+    pub redactions: HashMap<String, u32>,
+    /// Token counts of the fine-tuning entries (only if a vocabulary has been configured)
+    pub tokens: TokenStats,
+}
+
+/// Token counts of the fine-tuning entries
+#[derive(Clone, Default)]
+pub struct TokenStats {
+    /// Amount of entries whose tokens were counted
+    pub entries: usize,
+    /// Total amount of tokens of all entries
+    pub total: usize,
+    /// Amount of tokens of the longest entry
+    pub max: usize,
+    /// Amount of prompts that were truncated to the token budget
+    pub truncated_prompts: usize,
+    /// Amount of tokens of the training entries (including oversampled duplicates), per epoch
+    pub training: usize,
+    /// Amount of epochs the model is trained for
+    pub epochs: u32,
+    /// Estimated costs of the training, if a price has been configured
+    pub training_cost: Option<f64>,
 }
 
 impl<'a> Stats {
     pub fn new() -> Self {
         Stats {
             distribution: HashMap::new(),
+            category_samples: HashMap::new(),
+            title_conflicts: HashMap::new(),
+            redactions: HashMap::new(),
+            tokens: TokenStats::default(),
         }
     }
 
@@ -25,10 +81,43 @@ impl<'a> Stats {
     /// # Arguments
     ///
     /// - `key`: Key which occurrence has to be increased
+    /// - `category`: OpenAI category-ID the key has been mapped to
     pub fn inc_distribution<'b>(&'b mut self, key: &'a String, category: usize) {
         let display_name: String = format!("{} [{}]", key, category);
         let group_count = self.distribution.get(&display_name).unwrap_or(&0);
         self.distribution.insert(display_name, group_count + 1);
+        *self.category_samples.entry(category).or_insert(0) += 1;
+    }
+
+    /// Increase the amount of titles that were assigned to the given combination of groups by one
+    ///
+    /// # Arguments
+    ///
+    /// - `group_names`: Names of the groups, the title was assigned to
+    pub fn inc_title_conflicts(&mut self, group_names: &[String]) {
+        let display_name = group_names.join(" <> ");
+        *self.title_conflicts.entry(display_name).or_insert(0) += 1;
+    }
+
+    /// Increase the amount of values that were redacted by a rule
+    ///
+    /// # Arguments
+    ///
+    /// - `rule`: Name of the redaction rule
+    /// - `amount`: Amount of values that were redacted
+    pub fn inc_redactions(&mut self, rule: &str, amount: u32) {
+        *self.redactions.entry(rule.to_string()).or_insert(0) += amount;
+    }
+
+    /// Adds the tokens of a fine-tuning entry
+    ///
+    /// # Arguments
+    ///
+    /// - `amount`: Amount of tokens of the entry
+    pub fn add_entry_tokens(&mut self, amount: usize) {
+        self.tokens.entries += 1;
+        self.tokens.total += amount;
+        self.tokens.max = cmp::max(self.tokens.max, amount);
     }
 
     /// Prints stats to console.
@@ -36,9 +125,58 @@ impl<'a> Stats {
     /// This contains:
     ///
     /// - A histogram of the distribution of keys
+    /// - A histogram of the groups between which titles were conflicting (if there were any)
+    /// - A histogram of the redactions per rule (if there were any)
+    /// - The token counts and the estimated costs of the training (if tokens were counted)
     pub fn print_stats(&self) {
-        print_key_histogram(&self.distribution);
+        println!("{} distinct categories are in use", self.distribution.len());
+        print_key_histogram(&self.distribution, " Assignment group distribution ");
         println!();
+        if !self.title_conflicts.is_empty() {
+            let amount_conflicts: usize = self.title_conflicts.values().sum();
+            println!(
+                "{} titles were assigned to different groups",
+                amount_conflicts
+            );
+            print_key_histogram(&self.title_conflicts, " Conflicting assignment groups ");
+            println!();
+        }
+        if !self.redactions.is_empty() {
+            let amount_redactions: u32 = self.redactions.values().sum();
+            println!("{} values were redacted", amount_redactions);
+            print_key_histogram(&self.redactions, " Redactions ");
+            println!();
+        }
+        if self.tokens.entries > 0 {
+            self.print_token_stats();
+        }
+    }
+
+    /// Prints the token counts and the estimated costs of the training
+    fn print_token_stats(&self) {
+        let tokens = &self.tokens;
+        println!(
+            "{} tokens in {} entries (average: {:.1}, max: {})",
+            tokens.total,
+            tokens.entries,
+            tokens.total as f64 / tokens.entries as f64,
+            tokens.max
+        );
+        if tokens.truncated_prompts > 0 {
+            println!(
+                "{} prompts were truncated to the token budget",
+                tokens.truncated_prompts
+            );
+        }
+        println!(
+            "{} training tokens per epoch, {} tokens for {} epoch(s)",
+            tokens.training,
+            tokens.training * tokens.epochs as usize,
+            tokens.epochs
+        );
+        if let Some(training_cost) = tokens.training_cost {
+            println!("Estimated training cost: {:.2}", training_cost);
+        }
     }
 }
 
@@ -66,22 +204,18 @@ fn get_terminal_width() -> u16 {
 /// # Arguments
 ///
 /// - `distribution`: distribution of keys
-fn print_key_histogram(distribution: &HashMap<String, u16>) {
-    let mut ordered_entries: Vec<(&String, &u16)> = distribution.iter().collect();
+/// - `title`: title of the histogram
+fn print_key_histogram<T: Amount>(distribution: &HashMap<String, T>, title: &str) {
+    let mut ordered_entries: Vec<(&String, &T)> = distribution.iter().collect();
     ordered_entries.sort_by(|(_, amount1), (_, amount2)| amount2.cmp(amount1));
     let terminal_width = get_terminal_width();
-    println!("{} distinct categories are in use", ordered_entries.len());
     // Print header
-    println!(
-        "\n{:=^width$}",
-        " Assignment group distribution ",
-        width = terminal_width as usize
-    );
+    println!("\n{:=^width$}", title, width = terminal_width as usize);
     // Print histogram
     let (max_key_len, max_amount) = get_max_values(&ordered_entries);
     for (name, amount) in &ordered_entries {
-        let available_width_for_histogram = terminal_width - max_key_len - 2;
-        let histogram_padding = (**amount as f64 / max_amount as f64
+        let available_width_for_histogram = terminal_width.saturating_sub(max_key_len + 2);
+        let histogram_padding = ((**amount).as_f64() / max_amount.as_f64()
             * available_width_for_histogram as f64)
             .round() as usize;
         println!(
@@ -106,9 +240,9 @@ fn print_key_histogram(distribution: &HashMap<String, u16>) {
 ///
 /// - `max_key_len`: Length of the longest key
 /// - `max_amount`: Highest amount of occurrences
-fn get_max_values(entries: &Vec<(&String, &u16)>) -> (u16, u16) {
+fn get_max_values<T: Amount>(entries: &Vec<(&String, &T)>) -> (u16, T) {
     let mut max_key_len: u16 = 0;
-    let mut max_amount: u16 = 0;
+    let mut max_amount: T = T::default();
     for (name, amount) in entries {
         max_key_len = cmp::max(max_key_len, name.len() as u16);
         max_amount = cmp::max(max_amount, **amount);
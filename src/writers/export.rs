@@ -0,0 +1,23 @@
+/// Stores SNOW (ServiceNow) exports, that have been fetched from an instance
+use crate::compression::FileWriter;
+use anyhow::{Context, Result};
+use std::io::Write;
+
+/// Writes a serialized export to a file. It is compressed, if the file extension denotes a compression (e.g. `.gz`)
+///
+/// # Arguments
+///
+/// - `export_raw`: The serialized export
+/// - `filename`: Filepath of the export
+///
+/// # Bails out when
+///
+/// - the file can't be written
+pub fn write_export(export_raw: &str, filename: &String) -> Result<()> {
+    let mut writer = FileWriter::create(filename, None)?;
+    writer
+        .write_all(export_raw.as_bytes())
+        .and_then(|_| writer.finish())
+        .with_context(|| format!("Failed to write to: '{}'", &filename))?;
+    Ok(())
+}
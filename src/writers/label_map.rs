@@ -0,0 +1,22 @@
+/// Persists the label map
+use crate::loaders::label_map::LabelMap;
+use anyhow::{Context, Result};
+
+/// Writes the label map to a file
+///
+/// # Arguments
+///
+/// - `label_map`: The (updated) label map
+/// - `filename`: Filepath of the label map
+///
+/// # Bails out when
+///
+/// - the label map can't be serialized
+/// - the file can't be written
+pub fn write_label_map(label_map: &LabelMap, filename: &String) -> Result<()> {
+    let label_map_json =
+        serde_json::to_string_pretty(label_map).context("Failed to serialize label map")?;
+    std::fs::write(filename, label_map_json)
+        .with_context(|| format!("Failed to write to: '{}'", &filename))?;
+    Ok(())
+}
@@ -1,2 +1,5 @@
 /// Modules for data writers (e.g. to the file system)
+pub mod export;
+pub mod label_map;
 pub mod output;
+pub mod sidecar;
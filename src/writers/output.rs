@@ -1,13 +1,27 @@
 /// Creates files that can be used to fine tune OpenAI models
+use crate::compression::{split_compression_extension, Compression, FileWriter};
 use crate::mappers::output::FineTuningEntry;
 use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
 
-/// Writes the mapped result to the output-file
+/// Format of the written fine-tuning file
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Pretty-printed JSON array of all entries
+    Json,
+    /// One compact JSON object per line, as expected by the OpenAI fine-tuning API
+    Jsonl,
+}
+
+/// Writes the mapped result to the output-file. The entries are serialized directly into the (compressed) file
 ///
 /// # Arguments
 ///
 /// - `result`: Mapped result
 /// - `filename`: Output-filepath
+/// - `format`: Format in which the result has to be written
+/// - `compression`: Compression of the output-file. Detected by the file extension, if not set
 ///
 /// # Returns
 ///
@@ -17,10 +31,94 @@ use anyhow::{Context, Result};
 ///
 /// - the result can't be deserialized
 /// - the output-file can't be written
-pub fn write_result<'a>(result: &'a Vec<FineTuningEntry>, filename: &'a String) -> Result<usize> {
-    let result_json_result =
-        serde_json::to_string_pretty(&result).context("Failed to deserialize result")?;
-    std::fs::write(&filename, result_json_result)
+pub fn write_result<'a>(
+    result: &'a Vec<FineTuningEntry>,
+    filename: &'a String,
+    format: OutputFormat,
+    compression: Option<Compression>,
+) -> Result<usize> {
+    let mut writer = FileWriter::create(filename, compression)?;
+    match format {
+        OutputFormat::Json => serde_json::to_writer_pretty(&mut writer, &result)
+            .with_context(|| format!("Failed to write to: '{}'", &filename))?,
+        OutputFormat::Jsonl => write_jsonl(&mut writer, result)
+            .with_context(|| format!("Failed to write to: '{}'", &filename))?,
+    };
+    writer
+        .finish()
         .with_context(|| format!("Failed to write to: '{}'", &filename))?;
     Ok(result.len())
 }
+
+/// Determines the filepath of a data set (e.g. the validation set), by inserting its name before the extension of the
+/// output file (e.g. `out.jsonl` becomes `out.validation.jsonl` and `out.jsonl.gz` becomes `out.validation.jsonl.gz`)
+///
+/// # Arguments
+///
+/// - `file_output`: Filepath of the output file
+/// - `name`: Name of the data set
+///
+/// # Returns
+///
+/// The filepath of the data set
+pub fn data_set_filename(file_output: &str, name: &str) -> String {
+    let (file_output, compression_extension) = split_compression_extension(file_output);
+    let path = Path::new(file_output);
+    let extension = match path.extension() {
+        Some(extension) => format!("{}.{}", name, extension.to_string_lossy()),
+        None => name.to_string(),
+    };
+    format!(
+        "{}{}",
+        path.with_extension(extension).to_string_lossy(),
+        compression_extension
+    )
+}
+
+/// Writes the mapped result as JSONL (one compact entry per line)
+///
+/// # Arguments
+///
+/// - `writer`: Writer of the output-file
+/// - `result`: Mapped result
+///
+/// # Bails out when
+///
+/// - an entry can't be deserialized or written
+fn write_jsonl(writer: &mut impl Write, result: &[FineTuningEntry]) -> Result<()> {
+    for entry in result {
+        serde_json::to_writer(&mut *writer, entry).context("Failed to deserialize result")?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_entry_per_line() {
+        let entries = vec![
+            FineTuningEntry::Completion {
+                prompt: "Printer jammed\n\n###\n\n".to_string(),
+                completion: " 1".to_string(),
+            },
+            FineTuningEntry::Completion {
+                prompt: "VPN down\n\n###\n\n".to_string(),
+                completion: " 2".to_string(),
+            },
+        ];
+        let mut written: Vec<u8> = Vec::new();
+        write_jsonl(&mut written, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            concat!(
+                r#"{"prompt":"Printer jammed\n\n###\n\n","completion":" 1"}"#,
+                "\n",
+                r#"{"prompt":"VPN down\n\n###\n\n","completion":" 2"}"#,
+                "\n"
+            )
+        );
+    }
+}
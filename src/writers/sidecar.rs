@@ -0,0 +1,136 @@
+/// Creates the sidecar file, which links the category IDs to the SNOW assignment groups
+use crate::compression::split_compression_extension;
+use crate::mappers::sidecar::SidecarLabel;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Format of the sidecar file
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SidecarFormat {
+    /// JSON array of all labels
+    Json,
+    /// CSV with a header row
+    Csv,
+}
+
+/// Determines the filepath of the sidecar file, which is placed next to the output file.
+///
+/// The extension of the output file is replaced by `.labels.json` or `.labels.csv` (e.g. `train.jsonl` and
+/// `train.jsonl.gz` become `train.labels.json`).
+///
+/// # Arguments
+///
+/// - `file_output`: Filepath of the fine-tuning output file
+/// - `format`: Format of the sidecar file
+///
+/// # Returns
+///
+/// The filepath of the sidecar file
+pub fn sidecar_filename(file_output: &str, format: SidecarFormat) -> String {
+    let extension = match format {
+        SidecarFormat::Json => "labels.json",
+        SidecarFormat::Csv => "labels.csv",
+    };
+    let (file_output, _) = split_compression_extension(file_output);
+    Path::new(file_output)
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Writes the sidecar labels to a file
+///
+/// # Arguments
+///
+/// - `labels`: Labels of the assignment groups
+/// - `filename`: Filepath of the sidecar file
+/// - `format`: Format in which the labels have to be written
+///
+/// # Bails out when
+///
+/// - the labels can't be serialized
+/// - the file can't be written
+pub fn write_sidecar(
+    labels: &Vec<SidecarLabel>,
+    filename: &String,
+    format: SidecarFormat,
+) -> Result<()> {
+    let labels_serialized = match format {
+        SidecarFormat::Json => {
+            serde_json::to_string_pretty(labels).context("Failed to serialize labels")?
+        }
+        SidecarFormat::Csv => serialize_csv(labels)?,
+    };
+    std::fs::write(filename, labels_serialized)
+        .with_context(|| format!("Failed to write to: '{}'", &filename))?;
+    Ok(())
+}
+
+/// Serializes the labels to CSV
+///
+/// # Arguments
+///
+/// - `labels`: Labels of the assignment groups
+///
+/// # Returns
+///
+/// The CSV, including a header row
+///
+/// # Bails out when
+///
+/// - a label can't be serialized
+fn serialize_csv(labels: &[SidecarLabel]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for label in labels {
+        writer
+            .serialize(label)
+            .context("Failed to serialize labels")?;
+    }
+    let labels_csv = writer.into_inner().context("Failed to serialize labels")?;
+    String::from_utf8(labels_csv).context("Failed to serialize labels")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_sidecar_next_to_output() {
+        assert_eq!(
+            sidecar_filename("train.jsonl", SidecarFormat::Json),
+            "train.labels.json"
+        );
+        assert_eq!(
+            sidecar_filename("out/train.jsonl.gz", SidecarFormat::Json),
+            "out/train.labels.json"
+        );
+        assert_eq!(
+            sidecar_filename("train.jsonl.zst", SidecarFormat::Csv),
+            "train.labels.csv"
+        );
+    }
+
+    #[test]
+    fn serializes_labels_as_csv() {
+        let labels = vec![
+            SidecarLabel {
+                category: 0,
+                label: Some("service-desk".to_string()),
+                sys_id: "g1".to_string(),
+                name: "Service Desk".to_string(),
+                samples: 2,
+            },
+            SidecarLabel {
+                category: 1,
+                label: None,
+                sys_id: "g2".to_string(),
+                name: "Network, LAN".to_string(),
+                samples: 0,
+            },
+        ];
+        assert_eq!(
+            serialize_csv(&labels).unwrap(),
+            "category,label,sys_id,name,samples\n0,service-desk,g1,Service Desk,2\n1,,g2,\"Network, LAN\",0\n"
+        );
+    }
+}